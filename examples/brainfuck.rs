@@ -59,6 +59,10 @@ pub fn op_loop_start(vm: &mut Brainfuck, _opcode: u8) {
     }
 }
 
+pub fn op_comment(_vm: &mut Brainfuck, _opcode: u8) {
+    // Every other character is a comment
+}
+
 pub fn op_loop_end(vm: &mut Brainfuck, _opcode: u8) {
     if vm.memory[vm.ptr] != 0 {
         // Jump backward to matching [
@@ -80,6 +84,9 @@ archibald::instruction_table! {
 
     dispatcher = dispatch;
     context = Brainfuck;
+    strategy = table;
+    coverage = report;
+    fallback = op_comment;
 
     // Brainfuck commands map to their ASCII values
     "00111110" => op_inc_ptr;       // '>' (0x3E = 62 = 0b00111110)
@@ -93,21 +100,15 @@ archibald::instruction_table! {
 }
 
 fn main() {
-    let program = b"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+    assert_eq!(UNHANDLED_OPCODES.len(), 256 - 8);
+
+    let program = b"Hello World! ++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
 
     let mut vm = Brainfuck::new(program);
 
     while vm.pc < vm.program.len() {
         let opcode = vm.program[vm.pc];
-
-        // Only dispatch valid brainfuck commands, skip others
-        if matches!(
-            opcode,
-            b'>' | b'<' | b'+' | b'-' | b'.' | b',' | b'[' | b']'
-        ) {
-            dispatch(&mut vm, opcode);
-        }
-
+        dispatch(&mut vm, opcode);
         vm.pc += 1;
     }
 }
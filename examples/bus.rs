@@ -0,0 +1,82 @@
+pub trait Bus {
+    fn read(&mut self, addr: u8) -> u8;
+    fn write(&mut self, addr: u8, value: u8);
+}
+
+pub struct Ram {
+    pub bytes: [u8; 16],
+    pub accesses: usize,
+}
+
+impl Bus for Ram {
+    fn read(&mut self, addr: u8) -> u8 {
+        self.accesses += 1;
+        self.bytes[addr as usize]
+    }
+
+    fn write(&mut self, addr: u8, value: u8) {
+        self.accesses += 1;
+        self.bytes[addr as usize] = value;
+    }
+}
+
+// The CPU only holds its registers, memory is borrowed separately
+#[derive(Default)]
+pub struct Cpu {
+    pub acc: u8,
+}
+
+pub fn op_load(cpu: &mut Cpu, bus: &mut impl Bus, _opcode: u8, addr: u8) -> u32 {
+    println!("lda {:#x}", addr);
+    cpu.acc = bus.read(addr);
+    2
+}
+
+pub fn op_store(cpu: &mut Cpu, bus: &mut impl Bus, _opcode: u8, addr: u8) -> u32 {
+    println!("sta {:#x}", addr);
+    bus.write(addr, cpu.acc);
+    2
+}
+
+pub fn op_inc(cpu: &mut Cpu, _bus: &mut impl Bus, _opcode: u8) -> u32 {
+    println!("inc");
+    cpu.acc = cpu.acc.wrapping_add(1);
+    1
+}
+
+// Every handler receives the CPU and the bus, in the order of the signature
+archibald::instruction_table! {
+    type Opcode = u8;
+
+    dispatcher = dispatch;
+    signature = fn(cpu: &mut Cpu, bus: &mut impl Bus, opcode) -> u32;
+
+    "0001'{addr:4}" => op_load(addr);
+    "0010'{addr:4}" => op_store(addr);
+    "0011'0000" => op_inc;
+}
+
+fn main() {
+    let program = [
+        0x1F, // LDA 0xF
+        0x30, // INC
+        0x30, // INC
+        0x2E, // STA 0xE
+    ];
+
+    let mut cpu = Cpu::default();
+    let mut ram = Ram {
+        bytes: [0; 16],
+        accesses: 0,
+    };
+    ram.bytes[0xF] = 40;
+
+    let mut cycles = 0;
+    for opcode in program {
+        cycles += dispatch(&mut cpu, &mut ram, opcode);
+    }
+
+    assert_eq!(ram.bytes[0xE], 42);
+    assert_eq!(ram.accesses, 2);
+    assert_eq!(cycles, 6);
+}
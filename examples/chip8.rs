@@ -0,0 +1,127 @@
+pub struct Chip8 {
+    pub v: [u8; 16],
+    pub pc: u16,
+    pub memory: Vec<u8>,
+    pub screen_cleared: bool,
+}
+
+impl Chip8 {
+    pub fn new(program: &[u8]) -> Self {
+        Chip8 {
+            v: [0; 16],
+            pc: 0,
+            memory: program.to_vec(),
+            screen_cleared: false,
+        }
+    }
+
+    #[inline(always)]
+    fn fetch16(&self, addr: u16) -> u16 {
+        let addr = addr as usize;
+        u16::from_be_bytes([self.memory[addr], self.memory[addr + 1]])
+    }
+}
+
+pub fn op_cls(chip: &mut Chip8, _opcode: u16) {
+    println!("cls");
+    chip.screen_cleared = true;
+}
+
+pub fn op_sys(_chip: &mut Chip8, _opcode: u16, addr: u16) {
+    // Machine code routines are ignored by modern interpreters
+    println!("sys {:#05x}", addr);
+}
+
+pub fn op_jump(chip: &mut Chip8, _opcode: u16, addr: u16) {
+    println!("jp {:#05x}", addr);
+    chip.pc = addr;
+}
+
+pub fn op_skip_eq<const X: u8>(chip: &mut Chip8, _opcode: u16, kk: u8) {
+    println!("se v{:x}, {:#04x}", X, kk);
+    if chip.v[X as usize] == kk {
+        chip.pc += 2;
+    }
+}
+
+pub fn op_load_imm<const X: u8>(chip: &mut Chip8, _opcode: u16, kk: u8) {
+    println!("ld v{:x}, {:#04x}", X, kk);
+    chip.v[X as usize] = kk;
+}
+
+pub fn op_add_imm<const X: u8>(chip: &mut Chip8, _opcode: u16, kk: u8) {
+    println!("add v{:x}, {:#04x}", X, kk);
+    chip.v[X as usize] = chip.v[X as usize].wrapping_add(kk);
+}
+
+pub fn op_load_reg<const X: u8, const Y: u8>(chip: &mut Chip8, _opcode: u16) {
+    println!("ld v{:x}, v{:x}", X, Y);
+    chip.v[X as usize] = chip.v[Y as usize];
+}
+
+pub fn op_add_reg<const X: u8, const Y: u8>(chip: &mut Chip8, _opcode: u16) {
+    println!("add v{:x}, v{:x}", X, Y);
+    let (result, carry) = chip.v[X as usize].overflowing_add(chip.v[Y as usize]);
+    chip.v[X as usize] = result;
+    chip.v[0xF] = carry as u8;
+}
+
+// 16-bit opcodes dispatched through a decision tree, immediates extracted at runtime
+archibald::instruction_table! {
+    type Opcode = u16;
+
+    dispatcher = dispatch;
+    context = Chip8;
+    strategy = tree;
+    priority = specificity;
+    try_dispatcher = true;
+
+    // Encoding formats shared by most CHIP-8 instructions
+    format XKK = "{group:4}'{vx:4}'{kk:8}";
+    format XYN = "{group:4}'{vx:4}'{vy:4}'{n:4}";
+
+    // 00E0 is more specific than 0nnn, so it wins regardless of the order
+    "0000'{nnn:12}" => op_sys(nnn);                       // 0nnn
+    "0000'0000'1110'0000" => op_cls;                      // 00E0
+    "0001'{nnn:12}" => op_jump(nnn);                      // 1nnn
+    XKK(group = 0x3) => op_skip_eq<{vx}>(kk);             // 3xkk
+    XKK(group = 0x6) => op_load_imm<{vx}>(kk);            // 6xkk
+    XKK(group = 0x7) => op_add_imm<{vx}>(kk);             // 7xkk
+    XYN(group = 0x8, n = 0x0) => op_load_reg<{vx}, {vy}>; // 8xy0
+    XYN(group = 0x8, n = 0x4) => op_add_reg<{vx}, {vy}>;  // 8xy4
+}
+
+fn main() {
+    let program = [
+        0x00, 0xE0, // CLS
+        0x02, 0x00, // SYS 0x200 (ignored)
+        0x60, 0x05, // LD V0, 0x05
+        0x61, 0x07, // LD V1, 0x07
+        0x80, 0x14, // ADD V0, V1
+        0x30, 0x0C, // SE V0, 0x0C
+        0x12, 0x10, // JP 0x010 (skipped)
+        0x82, 0x00, // LD V2, V0
+        0x72, 0xFF, // ADD V2, 0xFF
+        0xF0, 0x0A, // LD V0, K (not implemented)
+    ];
+
+    // Stop at the first instruction the table doesn't implement
+    let mut trap = None;
+    let mut chip = Chip8::new(&program);
+    while (chip.pc as usize) < program.len() {
+        let opcode = chip.fetch16(chip.pc);
+        chip.pc += 2;
+        if let Err(unhandled) = try_dispatch(&mut chip, opcode) {
+            println!("{}", unhandled);
+            trap = Some(unhandled);
+            break;
+        }
+    }
+
+    assert_eq!(trap, Some(DispatchUnhandled(0xF00A)));
+
+    assert!(chip.screen_cleared);
+    assert_eq!(chip.v[0], 12);
+    assert_eq!(chip.v[1], 7);
+    assert_eq!(chip.v[2], 11);
+}
@@ -37,6 +37,11 @@ archibald::instruction_table! {
     "01mm'____" => handler<{m}> where {
         m: Mode = { 0b00 => A, 0b01 => B, 0b10 => C, 0b11 => D }
     };
+
+    // Constant binding without a pattern variable
+    "1000'____" => handler<{m}> where {
+        const m: Mode = D
+    };
 }
 
 fn main() {
@@ -53,4 +58,7 @@ fn main() {
     dispatch(&mut ctx, 0b0101_0000); // Mode::B
     dispatch(&mut ctx, 0b0110_0000); // Mode::C
     dispatch(&mut ctx, 0b0111_0000); // Mode::D
+
+    println!("Testing constant binding syntax:");
+    dispatch(&mut ctx, 0b1000_0000); // Mode::D
 }
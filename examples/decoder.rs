@@ -0,0 +1,96 @@
+#![feature(adt_const_params)]
+
+use std::marker::ConstParamTy;
+
+#[derive(ConstParamTy, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+}
+
+pub const fn is_set(bit: u8) -> bool {
+    bit != 0
+}
+
+// A decode-only table, no handlers are needed
+archibald::instruction_table! {
+    type Opcode = u8;
+
+    decoder = decode -> Instruction;
+    encoder = encode;
+
+    "00rr'{imm:4}" => load<{r}>(imm) where {
+        r: Register = { 0b00 => A, 0b01 => B, 0b10 => C, 0b11 => D }
+    };
+    "01dd'ss__" => add<{d}, {s}> where {
+        d: Register = { 0b00 => A, 0b01 => B, 0b10 => C, 0b11 => D },
+        s: Register = { 0b00 => A, 0b01 => B, 0b10 => C, 0b11 => D }
+    };
+    "10{off:5}c" => branch<{c}>(off as i8) where {
+        c: bool = is_set(c)
+    };
+    // A second entry of `load`, with raw register numbers, decodes into `Load2`
+    "1101'rr{imm:2}" => load<{r}>(imm);
+    "1110'0000" => nop;
+    #[allow(overlap)]
+    "1110'____" => any;
+    "1111'1111" => halt;
+}
+
+fn main() {
+    let program = [
+        0b0000_0101, // LD A, 5
+        0b0100_0100, // ADD A, B
+        0b1011_1101, // BRANCH.C -2
+        0b1100_0000, // Not an instruction
+        0xFF,        // HALT
+    ];
+
+    // A tracer inspecting the program without running it
+    let trace: Vec<Option<Instruction>> = program.iter().map(|&opcode| decode(opcode)).collect();
+    for (opcode, instruction) in program.iter().zip(&trace) {
+        println!("{:#04x}: {:?}", opcode, instruction);
+    }
+
+    assert_eq!(
+        trace,
+        [
+            Some(Instruction::Load {
+                r: Register::A,
+                imm: 5
+            }),
+            Some(Instruction::Add {
+                d: Register::A,
+                s: Register::B
+            }),
+            Some(Instruction::Branch { c: true, off: -2 }),
+            None,
+            Some(Instruction::Halt),
+        ]
+    );
+
+    // An assembler writing the program back from its instructions
+    let assembled: Vec<u8> = trace
+        .iter()
+        .flatten()
+        .map(|instruction| instruction.encode().unwrap())
+        .collect();
+    assert_eq!(assembled, [0x05, 0x44, 0xBD, 0xFF]);
+
+    // Branch offsets are 5 bits wide
+    let far = Instruction::Branch { c: false, off: 20 };
+    assert_eq!(far.encode(), Err(InstructionUnencodable(far)));
+    println!("{}", far.encode().unwrap_err());
+
+    // Zero wildcard bits would decode as `nop`, so `any` is encoded with other bits
+    assert_eq!(Instruction::Nop.encode(), Ok(0xE0));
+    assert_eq!(Instruction::Any.encode(), Ok(0xE1));
+    assert_eq!(decode(0xE1u8), Some(Instruction::Any));
+
+    // Every entry has its own variant, even when it reuses a handler
+    let short = Instruction::Load2 { r: 2, imm: 1 };
+    assert_eq!(decode(0xD9u8), Some(short));
+    assert_eq!(short.encode(), Ok(0xD9));
+}
@@ -0,0 +1,66 @@
+pub trait Bus {
+    fn read(&self, addr: u8) -> u8;
+}
+
+pub struct Rom(pub Vec<u8>);
+
+impl Bus for Rom {
+    fn read(&self, addr: u8) -> u8 {
+        self.0[addr as usize]
+    }
+}
+
+// Open bus, every read returns the same value
+pub struct OpenBus;
+
+impl Bus for OpenBus {
+    fn read(&self, _addr: u8) -> u8 {
+        0xFF
+    }
+}
+
+pub struct Cpu<B: Bus> {
+    pub regs: [u8; 4],
+    pub bus: B,
+}
+
+// The dispatcher's generics come first, followed by the const generics of the entry
+pub fn op_load<B: Bus, const R: u8>(cpu: &mut Cpu<B>, _opcode: u8, addr: u8) {
+    println!("ld r{}, [{:#x}]", R, addr);
+    cpu.regs[R as usize] = cpu.bus.read(addr);
+}
+
+pub fn op_clear<B: Bus>(cpu: &mut Cpu<B>, _opcode: u8) {
+    println!("clr");
+    cpu.regs = [0; 4];
+}
+
+archibald::instruction_table! {
+    type Opcode = u8;
+
+    dispatcher = dispatch<B: Bus>;
+    context = Cpu<B>;
+
+    "01rr'{addr:4}" => op_load<{r}>(addr);
+    "0000'0000" => op_clear;
+}
+
+fn main() {
+    let mut cpu = Cpu {
+        regs: [0; 4],
+        bus: Rom(vec![0x10, 0x20, 0x30, 0x40]),
+    };
+    dispatch(&mut cpu, 0b0100_0011); // LD R0, [3]
+    dispatch(&mut cpu, 0b0111_0001); // LD R3, [1]
+    assert_eq!(cpu.regs, [0x40, 0, 0, 0x20]);
+
+    // The same table drives a CPU with a different bus
+    let mut cpu = Cpu {
+        regs: [0; 4],
+        bus: OpenBus,
+    };
+    dispatch(&mut cpu, 0b0101_0000); // LD R1, [0]
+    assert_eq!(cpu.regs, [0, 0xFF, 0, 0]);
+    dispatch(&mut cpu, 0b0000_0000); // CLR
+    assert_eq!(cpu.regs, [0; 4]);
+}
@@ -0,0 +1,91 @@
+#![feature(adt_const_params)]
+
+use std::marker::ConstParamTy;
+
+#[derive(ConstParamTy, PartialEq, Eq, Debug)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+}
+
+// The macro declares `pub trait CpuHandlers` with one method per handler:
+//
+//     fn load<const R: Register>(&mut self, opcode: u8, imm: u8);
+//     fn add<const D: Register, const S: Register>(&mut self, opcode: u8);
+//     fn halt(&mut self, opcode: u8);
+archibald::instruction_table! {
+    type Opcode = u8;
+
+    dispatcher = dispatch;
+    handler_trait = CpuHandlers;
+
+    "00rr'{imm:4}" => load<{r}>(imm) where {
+        r: Register = { 0b00 => A, 0b01 => B, 0b10 => C, 0b11 => D }
+    };
+    "01dd'ss__" => add<{d}, {s}> where {
+        d: Register = { 0b00 => A, 0b01 => B, 0b10 => C, 0b11 => D },
+        s: Register = { 0b00 => A, 0b01 => B, 0b10 => C, 0b11 => D }
+    };
+    "1111'1111" => halt;
+}
+
+#[derive(Default)]
+pub struct Cpu {
+    pub regs: [u8; 4],
+    pub halted: bool,
+}
+
+impl CpuHandlers for Cpu {
+    fn load<const R: Register>(&mut self, _opcode: u8, imm: u8) {
+        self.regs[R as usize] = imm;
+    }
+
+    fn add<const D: Register, const S: Register>(&mut self, _opcode: u8) {
+        self.regs[D as usize] = self.regs[D as usize].wrapping_add(self.regs[S as usize]);
+    }
+
+    fn halt(&mut self, _opcode: u8) {
+        self.halted = true;
+    }
+}
+
+// The same table drives a mock that records the decoded instructions
+#[derive(Default)]
+pub struct Trace(pub Vec<String>);
+
+impl CpuHandlers for Trace {
+    fn load<const R: Register>(&mut self, _opcode: u8, imm: u8) {
+        self.0.push(format!("ld {:?}, {}", R, imm));
+    }
+
+    fn add<const D: Register, const S: Register>(&mut self, _opcode: u8) {
+        self.0.push(format!("add {:?}, {:?}", D, S));
+    }
+
+    fn halt(&mut self, _opcode: u8) {
+        self.0.push("halt".to_string());
+    }
+}
+
+fn main() {
+    let program = [
+        0b0000_0101, // LD A, 5
+        0b0001_0111, // LD B, 7
+        0b0100_0100, // ADD A, B
+        0xFF,        // HALT
+    ];
+
+    let mut cpu = Cpu::default();
+    let mut trace = Trace::default();
+    for opcode in program {
+        dispatch(&mut cpu, opcode);
+        dispatch(&mut trace, opcode);
+    }
+
+    assert_eq!(cpu.regs, [12, 7, 0, 0]);
+    assert!(cpu.halted);
+    assert_eq!(trace.0, ["ld A, 5", "ld B, 7", "add A, B", "halt"]);
+    println!("{}", trace.0.join("\n"));
+}
@@ -0,0 +1,99 @@
+#![feature(adt_const_params)]
+
+use std::fmt::Write;
+use std::marker::ConstParamTy;
+
+#[derive(ConstParamTy, PartialEq, Eq, Debug)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+}
+
+#[derive(Default)]
+pub struct Cpu {
+    pub regs: [u8; 4],
+}
+
+// Handlers executing instructions, returning the cycles taken
+mod exec_ops {
+    use super::{Cpu, Register};
+
+    pub fn load<const R: Register>(cpu: &mut Cpu, _opcode: u8, imm: u8) -> u32 {
+        cpu.regs[R as usize] = imm;
+        1
+    }
+
+    pub fn add<const D: Register, const S: Register>(cpu: &mut Cpu, _opcode: u8) -> u32 {
+        cpu.regs[D as usize] = cpu.regs[D as usize].wrapping_add(cpu.regs[S as usize]);
+        2
+    }
+
+    pub fn illegal(_cpu: &mut Cpu, opcode: u8) -> u32 {
+        panic!("illegal instruction {:#04x}", opcode);
+    }
+}
+
+// Handlers writing the assembly of instructions
+mod disasm_ops {
+    use super::{Register, Write};
+
+    pub fn load<const R: Register>(out: &mut String, _opcode: u8, imm: u8) {
+        writeln!(out, "ld {:?}, {}", R, imm).unwrap();
+    }
+
+    pub fn add<const D: Register, const S: Register>(out: &mut String, _opcode: u8) {
+        writeln!(out, "add {:?}, {:?}", D, S).unwrap();
+    }
+}
+
+// The same entries drive both dispatchers, each with its own context and return type
+archibald::instruction_table! {
+    type Opcode = u8;
+
+    dispatcher exec = execute in exec_ops;
+    dispatcher disasm = disassemble in disasm_ops;
+
+    context exec = Cpu;
+    returns exec = u32;
+    fallback exec = illegal;
+    strategy exec = table;
+
+    context disasm = String;
+    fallback disasm = {
+        writeln!(ctx, ".byte {:#04x}", opcode).unwrap()
+    };
+
+    "00rr'{imm:4}" => load<{r}>(imm) where {
+        r: Register = { 0b00 => A, 0b01 => B, 0b10 => C, 0b11 => D }
+    };
+    "01dd'ss__" => add<{d}, {s}> where {
+        d: Register = { 0b00 => A, 0b01 => B, 0b10 => C, 0b11 => D },
+        s: Register = { 0b00 => A, 0b01 => B, 0b10 => C, 0b11 => D }
+    };
+}
+
+fn main() {
+    let program = [
+        0b0000_0101, // LD A, 5
+        0b0001_0111, // LD B, 7
+        0b0100_0100, // ADD A, B
+        0xFF,        // Not an instruction
+    ];
+
+    let mut listing = String::new();
+    for opcode in program {
+        disassemble(&mut listing, opcode);
+    }
+    print!("{}", listing);
+    assert_eq!(listing, "ld A, 5\nld B, 7\nadd A, B\n.byte 0xff\n");
+
+    let mut cpu = Cpu::default();
+    let cycles: u32 = program[..3]
+        .iter()
+        .map(|&opcode| execute(&mut cpu, opcode))
+        .sum();
+    assert_eq!(cpu.regs, [12, 7, 0, 0]);
+    assert_eq!(cycles, 4);
+}
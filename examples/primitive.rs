@@ -1,5 +1,6 @@
 #![feature(adt_const_params)]
 
+#[derive(Default)]
 pub struct Cpu {
     pub reg: u8,
 }
@@ -33,6 +34,16 @@ pub fn alu<const OP: u8>(cpu: &mut Cpu, _opcode: u8) {
     cpu.reg = result;
 }
 
+pub fn set<const VALUE: u8>(cpu: &mut Cpu, _opcode: u8) {
+    println!("SET {}", VALUE);
+    cpu.reg = VALUE;
+}
+
+pub fn add_offset<const OFFSET: i8>(cpu: &mut Cpu, _opcode: u8) {
+    println!("ADD {}", OFFSET);
+    cpu.reg = cpu.reg.wrapping_add_signed(OFFSET);
+}
+
 pub const fn bit_to_bool(bit: u8) -> bool {
     bit != 0
 }
@@ -48,6 +59,14 @@ archibald::instruction_table! {
     };
 
     "0001'00oo" => alu<{o}>;
+
+    // Split variable, gathered MSB first: "v" is bits 4, 1 and 0
+    "001v'00vv" => set<{v}>;
+
+    // Declared primitive type instead of the default u8
+    "01oo'oooo" => add_offset<{o}> where {
+        o: i8
+    };
 }
 
 fn main() {
@@ -64,4 +83,13 @@ fn main() {
     dispatch(&mut cpu, 0b0001_0011); // DEC (op=3)
 
     println!("Final reg value: {}", cpu.reg);
+
+    println!("--- split u8 const generic ---");
+    dispatch(&mut cpu, 0b0011_0010); // SET 6 (v=0b110)
+    dispatch(&mut cpu, 0b0010_0011); // SET 3 (v=0b011)
+    assert_eq!(cpu.reg, 3);
+
+    println!("--- i8 const generic ---");
+    dispatch(&mut cpu, 0b0110_0000); // ADD 32
+    assert_eq!(cpu.reg, 35);
 }
@@ -0,0 +1,72 @@
+pub struct Cpu {
+    pub x: [u32; 32],
+}
+
+impl Cpu {
+    fn write<const RD: u8>(&mut self, value: u32) {
+        // x0 is hardwired to zero
+        if RD != 0 {
+            self.x[RD as usize] = value;
+        }
+    }
+}
+
+pub fn add<const RD: u8>(cpu: &mut Cpu, _opcode: u32, rs1: u8, rs2: u8) {
+    cpu.write::<RD>(cpu.x[rs1 as usize].wrapping_add(cpu.x[rs2 as usize]));
+}
+
+pub fn sub<const RD: u8>(cpu: &mut Cpu, _opcode: u32, rs1: u8, rs2: u8) {
+    cpu.write::<RD>(cpu.x[rs1 as usize].wrapping_sub(cpu.x[rs2 as usize]));
+}
+
+pub fn addi<const RD: u8>(cpu: &mut Cpu, _opcode: u32, rs1: u8, imm: i16) {
+    cpu.write::<RD>(cpu.x[rs1 as usize].wrapping_add(imm as u32));
+}
+
+pub fn nop(_cpu: &mut Cpu, _opcode: u32) {}
+
+// A few RV32I instructions, only the destination register is expanded into const
+// generics, the source registers and immediates are matched like wildcards
+archibald::instruction_table! {
+    type Opcode = u32;
+
+    dispatcher = execute;
+    context = Cpu;
+    strategy = tree;
+
+    format R = "{funct7:7}{rs2:5}{rs1:5}{funct3:3}{rd:5}{opcode:7}";
+    format I = "{imm:12}{rs1:5}{funct3:3}{rd:5}{opcode:7}";
+
+    "0000000{rs2:5}{rs1:5}000{rd:5}0110011" => add<{rd}>(rs1, rs2)
+        as "add x{rd}, x{rs1}, x{rs2}";
+    R(funct7 = 0b0100000, funct3 = 0b000, opcode = 0b0110011) => sub<{rd}>(rs1, rs2)
+        as "sub x{rd}, x{rs1}, x{rs2}";
+    // addi x0, x0, 0 is the canonical nop
+    "0000'0000'0000'0000'0000'0000'0001'0011" => nop as "nop";
+    #[allow(overlap)]
+    I(funct3 = 0b000, opcode = 0b0010011) => addi<{rd}>(rs1, imm as i16)
+        as "addi x{rd}, x{rs1}, {imm}";
+}
+
+fn main() {
+    let program: [u32; 5] = [
+        0x0050_0093, // addi x1, x0, 5
+        0xFFD0_0113, // addi x2, x0, -3
+        0x0020_81B3, // add x3, x1, x2
+        0x4020_8233, // sub x4, x1, x2
+        0x0000_0013, // nop
+    ];
+
+    let mut cpu = Cpu { x: [0; 32] };
+    for &opcode in &program {
+        let mut line = String::new();
+        disassemble(opcode, &mut line).unwrap();
+        println!("{:08x}: {}", opcode, line);
+        execute(&mut cpu, opcode);
+    }
+
+    assert_eq!(cpu.x[1], 5);
+    assert_eq!(cpu.x[2], -3i32 as u32);
+    assert_eq!(cpu.x[3], 2);
+    assert_eq!(cpu.x[4], 8);
+}
@@ -47,6 +47,27 @@ impl Vm {
             Register::R3 => self.r3 = value,
         }
     }
+
+    // Instruction handlers, returning the cycles taken
+
+    pub fn impl_add<const REG: Register>(&mut self, _opcode: u8, imm: u8) -> u32 {
+        let reg = self.get_reg(REG);
+        self.set_reg(REG, reg.wrapping_add(imm as u32));
+        1
+    }
+
+    pub fn impl_move<const DEST: Register, const SRC: Register>(&mut self, _opcode: u8) -> u32 {
+        let value = self.get_reg(SRC);
+        self.set_reg(DEST, value);
+        1
+    }
+
+    pub fn impl_load<const REG: Register>(&mut self, _opcode: u8, addr: u8) -> u32 {
+        // Memory accesses take an extra cycle
+        let value = self.fetch8(addr as usize) as u32;
+        self.set_reg(REG, value);
+        2
+    }
 }
 
 #[derive(ConstParamTy, PartialEq, Eq)]
@@ -68,48 +89,29 @@ impl std::fmt::Display for Register {
     }
 }
 
-pub fn impl_add<const REG: Register>(vm: &mut Vm, opcode: u8) {
-    println!("add {}, {}", REG, opcode & 0x0F);
-
-    let imm = (opcode & 0x0F) as u32;
-    let reg = vm.get_reg(REG);
-    vm.set_reg(REG, reg.wrapping_add(imm));
-}
-
-pub fn impl_move<const DEST: Register, const SRC: Register>(vm: &mut Vm, _opcode: u8) {
-    println!("move {}, {}", DEST, SRC);
-
-    let value = vm.get_reg(SRC);
-    vm.set_reg(DEST, value);
-}
-
-pub fn impl_load<const REG: Register>(vm: &mut Vm, opcode: u8) {
-    println!("load {}, {}", REG, opcode & 0x0F);
-
-    let addr = (opcode & 0x0F) as usize;
-    let value = vm.fetch8(addr) as u32;
-    vm.set_reg(REG, value);
-}
-
 archibald::instruction_table! {
     type Opcode = u8;
 
     dispatcher = dispatch;
     context = Vm;
+    returns = u32;     // Cycles taken by the instruction
+    handlers = method; // Called as vm.impl_add::<{ Register::R0 }>(opcode, imm)
+
+    // The syntax strings generate `disassemble`, registers are rendered by `Display`
 
     // ADD r0-r3, imm
-    "11rr'____" => impl_add<{r}> where {
+    "11rr'{imm:4}" => impl_add<{r}>(imm) as "add {r}, #{imm}" where {
         r: Register = { 0b00 => R0, 0b01 => R1, 0b10 => R2, 0b11 => R3 }
     };
 
     // MOVE r0-r3, r0-r3
-    "0010'ddss" => impl_move<{d}, {s}> where {
+    "0010'ddss" => impl_move<{d}, {s}> as "move {d}, {s}" where {
         d: Register = { 0b00 => R0, 0b01 => R1, 0b10 => R2, 0b11 => R3 },
         s: Register = { 0b00 => R0, 0b01 => R1, 0b10 => R2, 0b11 => R3 }
     };
 
     // LOAD r0-r3, imm
-    "01dd'____" => impl_load<{d}> where {
+    "01dd'{addr:4}" => impl_load<{d}>(addr) as "load {d}, [{addr:#x}]" where {
         d: Register = { 0b00 => R0, 0b01 => R1, 0b10 => R2, 0b11 => R3 }
     };
 }
@@ -123,13 +125,24 @@ fn main() {
         0x40, // LOAD R0, 0
     ];
 
+    let mut listing = String::new();
+    let mut cycles = 0;
     let mut vm = Vm::new(&program);
     while vm.pc < program.len() {
         let opcode = vm.fetch8(vm.pc);
-        dispatch(&mut vm, opcode);
+        disassemble(opcode, &mut listing).unwrap();
+        listing.push('\n');
+        cycles += dispatch(&mut vm, opcode);
         vm.pc += 1;
     }
+    print!("{}", listing);
+
+    assert_eq!(
+        listing,
+        "add r0, #10\nmove r1, r0\nadd r2, #4\nmove r3, r2\nload r0, [0x0]\n"
+    );
 
+    assert_eq!(cycles, 6);
     assert_eq!(vm.r1, 10);
     assert_eq!(vm.r2, 4);
     assert_eq!(vm.r3, 4);
@@ -16,17 +16,59 @@
 //!
 //! Patterns use the following characters:
 //! - `0`, `1` - Fixed bits that must match exactly
-//! - `a-z` - Variable bits (e.g., `rr`, `mm`) that are extracted and passed as const generics
+//! - `a-z` - Variable bits (e.g., `rr`, `mm`) that are extracted and passed as const generics.
+//!   A variable may be split across the pattern (e.g., `"i000'0iii"`), its bits are
+//!   gathered MSB first into a single value
+//! - `{name:width}` - Named variable with an explicit width (e.g., `{rs1:5}`), for
+//!   readable wide patterns like `"0000000{rs2:5}{rs1:5}000{rd:5}0110011"`. Named
+//!   variables are used like single letters in handler generics (`add<{rd}>`) and
+//!   where clauses. Fields the entry doesn't use, like `rs1` and `rs2` here, are
+//!   matched like wildcards instead of being expanded
 //! - `_`, `.` - Wildcard bits - useful for immediate values embedded in opcodes
 //! - `'` - Visual separator (ignored) - use for readability like `"1100'xxyy"`
 //!
+//! ## Instruction Formats
+//!
+//! ISAs like RISC-V or MIPS reuse a few encoding formats for hundreds of instructions.
+//! A format is declared once next to the configuration and entries then only fix the
+//! fields that identify the instruction. All other fields stay variables:
+//!
+//! ```ignore
+//! format R = "{funct7:7}{rs2:5}{rs1:5}{funct3:3}{rd:5}{opcode:7}";
+//!
+//! R(funct7 = 0b0000000, funct3 = 0b000, opcode = 0b0110011) => add<{rd}>;
+//! R(funct7 = 0b0100000, funct3 = 0b000, opcode = 0b0110011) => sub<{rd}>;
+//! ```
+//!
+//! An entry using a format behaves exactly like the full pattern string with the fixed
+//! fields written out as `0`/`1` bits. Only variables passed as const generics or bound
+//! in the where clause are expanded, `add` above has 32 arms for `rd` while `rs1` and
+//! `rs2` are matched like wildcards.
+//!
+//! ## Runtime Fields
+//!
+//! Wide immediates and offsets shouldn't be expanded into thousands of specializations.
+//! Variables listed in parentheses after the handler are extracted from the opcode by
+//! the dispatcher instead and passed as extra arguments after the opcode:
+//!
+//! ```ignore
+//! "0001'{imm:12}" => jmp(imm);                          // jmp(ctx, opcode, imm: u16)
+//! "11rr'{off:4}" => branch<{r}>(off as i8 * 2);         // sign-extended and scaled
+//! ```
+//!
+//! Without a type the smallest unsigned type that holds the field is used. Signed types
+//! sign-extend the field and `* n` multiplies it, e.g. for halfword branch offsets. A
+//! field that doesn't fit its type is a compile error.
+//!
 //! ## Usage
 //!
 //! This example demonstrates all major features:
 //!
 //! ```rust
+//! # #![feature(adt_const_params)]
 //! use archibald::instruction_table;
 //!
+//! # struct Cpu { regs: [u8; 4], memory: [u8; 16] }
 //! #[derive(Debug, PartialEq, Eq, core::marker::ConstParamTy)]
 //! enum Register { R0, R1, R2, R3 }
 //!
@@ -67,7 +109,7 @@
 //!         i: bool = bit_to_bool(i)
 //!     };
 //!
-//!     // Passthrough u8 - no where clause (currently always u8 regardless of Opcode type)
+//!     // Passthrough u8 - no where clause, smallest unsigned type that fits the field
 //!     "0100'00oo" => alu<{o}>;
 //! }
 //!
@@ -111,18 +153,19 @@
 //! and execute instructions. It takes a mutable reference to the provided context.
 //! The expanded function will look similar to this:
 //!
-//! ```rust
+//! ```ignore
 //! #[inline]
 //! pub fn dispatch(ctx: &mut (), opcode: u8) {
 //!     match opcode {
-//!         op if op & 240u8 == 0u8 => handler::<{ decode_mode(0u8) }>(ctx, opcode),
-//!         op if op & 240u8 == 16u8 => handler::<{ decode_mode(1u8) }>(ctx, opcode),
-//!         op if op & 240u8 == 32u8 => handler::<{ decode_mode(2u8) }>(ctx, opcode),
-//!         op if op & 240u8 == 48u8 => handler::<{ decode_mode(3u8) }>(ctx, opcode),
-//!         op if op & 240u8 == 64u8 => handler::<{ Mode::A }>(ctx, opcode),
-//!         op if op & 240u8 == 80u8 => handler::<{ Mode::B }>(ctx, opcode),
-//!         op if op & 240u8 == 96u8 => handler::<{ Mode::C }>(ctx, opcode),
-//!         op if op & 240u8 == 112u8 => handler::<{ Mode::D }>(ctx, opcode),
+//!         0u8..=15u8 => handler::<{ decode_mode(0u8) }>(ctx, opcode),
+//!         16u8..=31u8 => handler::<{ decode_mode(1u8) }>(ctx, opcode),
+//!         32u8..=47u8 => handler::<{ decode_mode(2u8) }>(ctx, opcode),
+//!         48u8..=63u8 => handler::<{ decode_mode(3u8) }>(ctx, opcode),
+//!         64u8..=79u8 => handler::<{ Mode::A }>(ctx, opcode),
+//!         80u8..=95u8 => handler::<{ Mode::B }>(ctx, opcode),
+//!         96u8..=111u8 => handler::<{ Mode::C }>(ctx, opcode),
+//!         112u8..=127u8 => handler::<{ Mode::D }>(ctx, opcode),
+//!         #[allow(unreachable_patterns)]
 //!         _ => {
 //!             ::core::panicking::panic_fmt(
 //!                 format_args!("Unhandled opcode: 0x{0:02X}", opcode),
@@ -132,32 +175,326 @@
 //! }
 //! ```
 //!
+//! ## Dispatch Strategies
+//!
+//! The dispatcher body can be generated by different backends, selected with the
+//! `strategy` configuration key:
+//!
+//! - `strategy = match;` (default) - A single `match`. Opcodes calling the same handler
+//!   instantiation are merged into or-patterns and ranges where that keeps the entry
+//!   priority, only wildcard patterns that don't fit a few ranges use masked guards
+//! - `strategy = table;` - A `static` table of function pointers with one slot per
+//!   opcode (u8 and u16 only). Each handler instantiation is stored at every opcode
+//!   it handles, so dispatching is a single indirect call
+//! - `strategy = tree;` - Nested `match` expressions that switch on the opcode bits
+//!   shared by the most patterns first, then recurse. Suited for wide (u16/u32)
+//!   tables with hundreds of arms, where a linear guard chain is too slow
+//! - `strategy = two_level(hi = 10);` - A first-level table indexed by the top `hi`
+//!   opcode bits. Each slot points either directly to a handler, when all opcodes
+//!   sharing those bits are handled the same way, or to a second-level table indexed
+//!   by the remaining bits. Identical second-level tables are shared, which keeps
+//!   m68k-style u16 tables small enough for the cache
+//! - `strategy = auto;` - Picks one of the above based on the opcode width, the number
+//!   of opcodes covered and the number of guarded (wildcard) arms. The choice and its
+//!   reason are documented on the dispatcher and exported as `STRATEGY_REPORT`:
+//!
+//! ```text
+//! strategy: two_level(hi = 8) (38.7% of the 16 bit opcode space is covered, a full table is too big)
+//! arms: 65 (15 guarded)
+//! handler instantiations: 30
+//! opcodes covered: 25330 of 65536
+//! table size: 2560 slots
+//! ```
+//!
+//! All strategies keep the same semantics: when patterns overlap, the first matching
+//! entry wins (the most specific one with `priority = specificity`).
+//!
+//! ## Coverage
+//!
+//! `coverage = report;` emits constants describing the opcodes no entry handles:
+//! `COVERAGE` (percentage of handled opcodes), `UNHANDLED_PATTERNS` (unhandled opcodes
+//! as `(mask, value)` pairs) and, for 8 and 16 bit tables, `UNHANDLED_OPCODES`.
+//! `coverage = require;` additionally fails compilation unless every opcode is handled.
+//!
+//! ## Signatures
+//!
+//! `context = Cpu;` makes the dispatcher and every handler take `(ctx: &mut Cpu, opcode)`.
+//! Other parameters are declared with a full signature instead, which is forwarded to
+//! every handler in order, e.g. to borrow the CPU and the bus separately:
+//!
+//! ```ignore
+//! signature = fn(cpu: &mut Cpu, bus: &mut impl Bus, opcode) -> u32;
+//! ```
+//!
+//! `opcode` marks the position of the opcode.
+//!
+//! Generic contexts need generics on the dispatcher, which are forwarded to every
+//! handler call ahead of the entry's const generics:
+//!
+//! ```ignore
+//! dispatcher = dispatch<B: Bus>;
+//! context = Cpu<B>;
+//!
+//! "01rr'{addr:4}" => load<{r}>(addr); // fn load<B: Bus, const R: u8>(cpu: &mut Cpu<B>, ...)
+//! ```
+//!
+//! Lifetimes (`dispatch<'a>` with `context = Machine<'a>`) are inferred instead. Handlers
+//! of generic dispatchers and dispatchers with `impl Trait` parameters can't be stored
+//! in static function-pointer tables, so `table` and `two_level` strategies are not
+//! available for them.
+//!
+//! ## Method Handlers
+//!
+//! With `handlers = method;`, or a `self.` prefix on a single entry, handlers are
+//! methods called on the first parameter: `"00rr'____" => self.add<{r}>;` calls
+//! `ctx.add::<{ Register::R0 }>(opcode)`. The generics of the dispatcher belong to the
+//! `impl` of the methods and aren't forwarded.
+//!
+//! ## Handler Traits
+//!
+//! `handler_trait = CpuHandlers;` generates a `pub trait CpuHandlers` declaring every
+//! handler of the table as a method, and the dispatcher takes any implementation of
+//! it: `dispatch(ctx: &mut impl CpuHandlers, opcode)`. Const generics are named after
+//! their variable in uppercase and take the type declared in the where clause, so
+//! every variable passed as a generic needs one (`r: Register = ...`). A missing
+//! handler is reported as a missing trait item, and one table can drive several
+//! implementations, e.g. a CPU and a mock for tests.
+//!
+//! ## Multiple Dispatchers
+//!
+//! One table can declare several labelled dispatchers, e.g. to execute and to
+//! disassemble the same instructions. Each resolves handler paths relative to its
+//! module (`in module`). Settings without a label apply to every dispatcher, labelled
+//! ones to a single dispatcher:
+//!
+//! ```ignore
+//! dispatcher exec = execute in exec_ops;        // exec_ops::add::<{ Register::R0 }>(cpu, opcode)
+//! dispatcher disasm = disassemble in disasm_ops;
+//! context exec = Cpu;
+//! returns exec = u32;
+//! context disasm = String;
+//! strategy exec = table;
+//! ```
+//!
+//! The dispatchers share the entries, their expansion and priority, as well as
+//! `coverage` and `handler_opcode`. Each can pick its own `strategy`, e.g. `table` for
+//! the executing dispatcher next to a generic one that only supports `match` and
+//! `tree`. The decoder and disassembler use the unlabelled `strategy`. With
+//! `strategy = auto`, the report of a labelled dispatcher is named after its label,
+//! e.g. `EXEC_STRATEGY_REPORT`.
+//!
+//! ## Decoder
+//!
+//! `decoder = decode -> Instruction;` generates `pub enum Instruction` with a variant
+//! per entry, named after its handler in UpperCamelCase, and
+//! `decode(opcode) -> Option<Instruction>` matching entries with the same priority as
+//! the dispatchers. Entries reusing a handler are numbered:
+//!
+//! ```ignore
+//! "01rr'{imm:4}" => load<{r}>(imm);   // Instruction::Load { r: Register, imm: u8 }
+//! "11rr'{imm:4}" => load<{r}>(imm);   // Instruction::Load2 { r: u8, imm: u8 }
+//! ```
+//!
+//! Fields carry the const generics, typed by the where clause, followed by the runtime
+//! fields. The enum derives `Debug`, `Clone`, `Copy`, `PartialEq` and `Eq`, which
+//! the field types must implement too. A table can have just a decoder and no
+//! dispatcher.
+//!
+//! ## Encoder
+//!
+//! `encoder = encode;` adds the inverse to the instruction enum, for assemblers and
+//! tests writing programs without hand-assembled opcodes:
+//!
+//! ```ignore
+//! Instruction::Load { r: Register::R1, imm: 7 }.encode()  // Ok(0b0101_0111)
+//! ```
+//!
+//! The expansions of the variant's entry are tried in priority order, placing the fixed
+//! bits, the bits of the generic values and the runtime fields. Wildcard bits are zero,
+//! unless an entry matched before claims that opcode, then bits no such entry matches
+//! are used. The first opcode that `decode` turns back into the same instruction is
+//! returned. Instructions without
+//! one, like an immediate that doesn't fit its field, are an
+//! `Err(InstructionUnencodable(insn))`, named after the instruction enum.
+//!
+//! ## Disassembler
+//!
+//! Entries can carry their assembly syntax after the handler, from which
+//! `disassemble(opcode, out: &mut impl fmt::Write) -> fmt::Result` is generated:
+//!
+//! ```ignore
+//! "11rr'{imm:4}" => add<{r}>(imm) as "add {r}, #{imm:#x}";
+//! ```
+//!
+//! Fields are written with `format!` syntax and name a pattern variable or binding,
+//! rendered by the `Display` impl of its value, `_` for the wildcard bits or `opcode`.
+//! Once an entry has a syntax string, every entry needs one. Opcodes that no entry
+//! matches are written as `(bad)`. `disassembler = name;` renames the function. It
+//! uses the unlabelled `strategy`, except that `table` and `two_level` can't store
+//! handlers for the generic output and fall back to `match`.
+//!
+//! ## Return Values
+//!
+//! With `returns = u32;` every handler returns a `u32` (e.g. the cycles it took), which
+//! the dispatcher passes on: `cycles += dispatch(&mut cpu, opcode);`. The return type
+//! can also be part of the `signature`.
+//!
+//! ## Unhandled Opcodes
+//!
+//! Opcodes that no entry matches panic by default. `fallback = illegal_instruction;`
+//! calls a handler with the usual signature for them instead, e.g. to raise a trap.
+//! Any other expression is returned as is, e.g. `fallback = 0;` with `returns = u32;`.
+//! Constants are written in braces, `fallback = { HALT };`, since a path is taken as
+//! a handler.
+//! `try_dispatcher = true;` also generates a `try_<dispatcher>` function that returns
+//! them as an `Err(DispatchUnhandled(opcode))`, with the error type named after the
+//! dispatcher generated next to it. The dispatcher doesn't call it, so parameters
+//! passed by value are only moved once:
+//!
+//! ```rust
+//! # use archibald::instruction_table;
+//! struct Trace(Vec<u8>);
+//!
+//! instruction_table! {
+//!     type Opcode = u8;
+//!
+//!     dispatcher = dispatch;
+//!     signature = fn(trace: Trace, opcode) -> Trace;
+//!     fallback = illegal;
+//!     try_dispatcher = true;
+//!
+//!     "0000'0001" => nop;
+//! }
+//!
+//! fn nop(mut trace: Trace, opcode: u8) -> Trace { trace.0.push(opcode); trace }
+//! fn illegal(mut trace: Trace, _opcode: u8) -> Trace { trace.0.push(0xFF); trace }
+//!
+//! let trace = dispatch(Trace(Vec::new()), 0x01u8);
+//! assert_eq!(dispatch(trace, 0x02u8).0, [0x01, 0xFF]);
+//! assert!(matches!(try_dispatch(Trace(Vec::new()), 0x02u8), Err(DispatchUnhandled(0x02))));
+//! ```
+//!
+//! ## Overlapping Entries
+//!
+//! Overlapping entries must be ordered from most to least specific. An entry that is
+//! completely shadowed by earlier entries is a compile error, a partly shadowed entry
+//! produces a warning. Mark intended overlaps to silence the warning:
+//!
+//! ```ignore
+//! "0101'____" => specific;
+//! #[allow(overlap)]
+//! "01__'____" => generic;
+//! ```
+//!
+//! With `priority = specificity;` entries are ordered by their number of fixed `0`/`1`
+//! bits instead, the most specific first, so they can be declared in any order.
+//! Variables don't count as fixed bits, even when they are expanded into const
+//! generics:
+//!
+//! ```rust
+//! # use archibald::instruction_table;
+//! # #[derive(Default)]
+//! # struct Cpu { acc: u8, halted: bool }
+//! instruction_table! {
+//!     type Opcode = u8;
+//!
+//!     dispatcher = dispatch;
+//!     context = Cpu;
+//!     priority = specificity;
+//!
+//!     "1111'rrrr" => op<{r}>;   // 4 fixed bits
+//!     "1111'0000" => halt;      // 8 fixed bits, wins for 0xF0
+//! }
+//!
+//! fn op<const R: u8>(cpu: &mut Cpu, _opcode: u8) { cpu.acc = R; }
+//! fn halt(cpu: &mut Cpu, _opcode: u8) { cpu.halted = true; }
+//!
+//! let mut cpu = Cpu::default();
+//! dispatch(&mut cpu, 0xF3u8);
+//! assert_eq!(cpu.acc, 3);
+//! dispatch(&mut cpu, 0xF0u8);
+//! assert!(cpu.halted);
+//! assert_eq!(cpu.acc, 3);
+//! ```
+//!
+//! Partial overlaps aren't warned about, but overlapping entries with the same number
+//! of fixed bits are ambiguous and a compile error.
+//!
 //! ## Notes
 //!
 //! - Requires nightly Rust with `#![feature(adt_const_params)]` for enum const generics
 //! - Enums used as const generics must implement `ConstParamTy`, `PartialEq`, and `Eq`
 //! - Const functions must be marked with `const fn`
-//! - Patterns must be exactly 8, 16, 32, or 64 bits (after removing `'` separators)
-//! - Variables without where clauses (passthrough) always generate `u8` literals
+//! - Patterns must be exactly 8, 16, 32, or 64 bits (after removing `'` separators) and
+//!   as wide as the opcode type
+//! - Variables without where clauses (passthrough) generate literals of the smallest
+//!   unsigned type that fits the field (`u8` up to 8 bits, `u16` up to 16 bits, ...).
+//!   Declare the type to override it, e.g. `where { o: u16 }` or `where { o: i8 = o }`.
+//!   A bit value that doesn't fit the declared type is a compile error
+//! - Where bindings must name a pattern variable. Constants that don't come from the
+//!   pattern are declared with `const`, e.g. `where { const m: Mode = Direct }`
+//! - Mistakes in the table are reported as compile errors pointing at the offending
+//!   pattern character, field, variable or mapping. All errors of a table are reported
+//!   at once
 
 use proc_macro::TokenStream;
-use proc_macro2::TokenStream as TokenStream2;
+use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::{format_ident, quote};
 use std::collections::HashMap;
 use syn::{
-    Ident, LitInt, LitStr, Path, Token, Type, braced,
+    Expr, GenericParam, Generics, Ident, LitBool, LitInt, LitStr, Path, Token, Type, braced,
+    ext::IdentExt,
+    parenthesized,
     parse::{Parse, ParseStream},
     parse_macro_input,
     punctuated::Punctuated,
     token,
 };
 
+/// A resolved const generic binding: `(var_name, var_ident, const_value)`
+type Binding = (String, Ident, TokenStream2);
+
+/// A fully expanded opcode value together with the bindings that produced it
+type OpcodeVariant = (u64, Vec<Binding>);
+
+/// A pattern variable being expanded: `(var_name, bit_positions, binding, literal_type)`
+type VariableInfo<'a> = (&'a str, &'a [u8], BindingValue, Ident);
+
+/// Collects errors so that all problems of a table are reported at once.
+#[derive(Default)]
+struct Errors(Option<syn::Error>);
+
+impl Errors {
+    /// Records an error.
+    fn push(&mut self, error: syn::Error) {
+        match &mut self.0 {
+            Some(errors) => errors.combine(error),
+            None => self.0 = Some(error),
+        }
+    }
+
+    /// Returns the value of `result`, or records its error and returns `None`.
+    fn check<T>(&mut self, result: syn::Result<T>) -> Option<T> {
+        result.map_err(|error| self.push(error)).ok()
+    }
+
+    /// Returns all recorded errors combined into one.
+    fn finish(self) -> syn::Result<()> {
+        match self.0 {
+            Some(errors) => Err(errors),
+            None => Ok(()),
+        }
+    }
+}
+
 /// A single bit-to-variant mapping used in manual enum mappings.
 ///
 /// Represents one entry in a manual mapping like `0b00 => R0`.
 /// Supports both `0b` prefixed and plain binary literals.
 #[derive(Clone)]
 struct BitMapping {
+    /// The bit pattern literal as written, for error reporting
+    lit: LitInt,
     /// The bit pattern as a binary string (e.g., "00", "01")
     bits: String,
     /// The enum variant identifier (e.g., `R0`)
@@ -176,10 +513,20 @@ impl Parse for BitMapping {
             // Assume it's already binary digits
             bits_str
         };
+        if !bits.chars().all(|c| c == '0' || c == '1') {
+            return Err(syn::Error::new(
+                bits_lit.span(),
+                "Expected a binary bit pattern like `0b01` or `01`",
+            ));
+        }
 
         input.parse::<Token![=>]>()?;
         let variant: Ident = input.parse()?;
-        Ok(BitMapping { bits, variant })
+        Ok(BitMapping {
+            lit: bits_lit,
+            bits,
+            variant,
+        })
     }
 }
 
@@ -201,10 +548,19 @@ enum BindingValue {
 /// Binds a pattern variable (like `mm` from `"00mm____"`) to either:
 /// - A manual enum mapping: `mm: Mode = { 0b00 => A, 0b01 => B, ... }`
 /// - A const function: `mm: Mode = decode_mode(mm)`
+/// - Its raw bits as a specific integer type: `o: u16` (same as `o: u16 = o`)
+///
+/// Bindings marked `const` don't belong to a pattern variable, they bind a constant
+/// value to a name every expanded variant of the entry can use:
+/// `const m: Mode = Mode::Direct`
 struct VariableBinding {
-    /// Variable name (e.g., "mm", "r", "dd")
-    name: String,
-    /// Optional type annotation for adding enum prefixes (e.g., `Register`, `Mode`, `m68k::Direction`)
+    /// Whether the binding is a constant (`const x: T = ...`) instead of a pattern variable
+    constant: bool,
+    /// Variable name (e.g., `mm`, `r`, `dd`)
+    name: Ident,
+    /// Optional type annotation for adding enum prefixes (e.g., `Register`, `Mode`, `m68k::Direction`).
+    /// A primitive integer type (e.g., `u16`, `i8`) also sets the type of the variable's
+    /// bit value literal
     enum_type: Option<Path>,
     /// The binding value (manual mapping or const function)
     value: BindingValue,
@@ -212,8 +568,11 @@ struct VariableBinding {
 
 impl Parse for VariableBinding {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let name_ident: Ident = input.parse()?;
-        let name = name_ident.to_string();
+        let constant = input.peek(Token![const]);
+        if constant {
+            input.parse::<Token![const]>()?;
+        }
+        let name: Ident = input.parse()?;
 
         // Optional type annotation
         let enum_type = if input.peek(Token![:]) {
@@ -247,6 +606,19 @@ impl Parse for VariableBinding {
             None
         };
 
+        // A type without a value passes the raw bits through: `o: u16`
+        if (input.is_empty() || input.peek(Token![,])) && !constant {
+            if enum_type.is_none() {
+                return Err(input.error(format!("expected `:` or `=` after '{}'", name)));
+            }
+            return Ok(VariableBinding {
+                constant,
+                value: BindingValue::ConstFn(quote! { #name }),
+                name,
+                enum_type,
+            });
+        }
+
         input.parse::<Token![=]>()?;
 
         let value = if input.peek(token::Brace) {
@@ -270,6 +642,7 @@ impl Parse for VariableBinding {
         };
 
         Ok(VariableBinding {
+            constant,
             name,
             enum_type,
             value,
@@ -308,15 +681,64 @@ impl Parse for WhereClause {
 /// - `move_reg<{d}, {s}>` - multiple variables
 /// - `alu<{op}>` - variable without where clause (primitive type)
 /// - `m68k::shift_left` - path to handler function
+/// - `jmp(imm)` - runtime field passed as an extra argument
+/// - `beq<{c}>(rs1, rs2, off as i16 * 2)` - const generics and typed, scaled runtime fields
+/// - `self.add<{r}>` - method called on the first parameter of the dispatcher
 struct HandlerSpec {
+    /// Whether the handler is a method called on the first parameter (`self.add`)
+    method: bool,
     /// Handler function name (can be a path like `m68k::shift_left`)
     name: Path,
-    /// Variable names from const generic arguments (e.g., ["r"], ["d", "s"])
-    generics: Vec<String>,
+    /// Variables from const generic arguments (e.g., `[r]`, `[d, s]`)
+    generics: Vec<Ident>,
+    /// Variables extracted at runtime and passed as extra arguments, in order
+    args: Vec<RuntimeArg>,
+}
+
+/// A pattern variable extracted at runtime and passed to the handler as an argument.
+///
+/// Syntax: `name`, `name as i16` or `name as i32 * 4`
+struct RuntimeArg {
+    /// Variable name from the pattern
+    name: Ident,
+    /// Integer type of the argument. Signed types sign-extend the field, without a
+    /// type the smallest unsigned type that fits the (scaled) field is used
+    ty: Option<Ident>,
+    /// Optional factor the field is multiplied with (e.g., 2 for halfword offsets)
+    scale: Option<LitInt>,
+}
+
+impl Parse for RuntimeArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name: Ident = input.parse()?;
+
+        let ty = if input.peek(Token![as]) {
+            input.parse::<Token![as]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
+        let scale = if input.peek(Token![*]) {
+            input.parse::<Token![*]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
+        Ok(RuntimeArg { name, ty, scale })
+    }
 }
 
 impl Parse for HandlerSpec {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        // self.add<{r}>
+        let method = input.peek(Token![self]) && input.peek2(Token![.]);
+        if method {
+            input.parse::<Token![self]>()?;
+            input.parse::<Token![.]>()?;
+        }
+
         // Parse path manually to avoid consuming the custom generic syntax <{var}>
         // We want to parse: `ident`, `ident::ident`, `ident::ident::ident`, etc.
         // But NOT the `<{...}>` part which comes after
@@ -329,8 +751,8 @@ impl Parse for HandlerSpec {
             arguments: syn::PathArguments::None,
         });
 
-        // Parse additional segments separated by `::`
-        while input.peek(Token![::]) {
+        // Parse additional segments separated by `::`, methods only have a name
+        while !method && input.peek(Token![::]) {
             input.parse::<Token![::]>()?;
             let ident: Ident = input.parse()?;
             segments.push(syn::PathSegment {
@@ -357,7 +779,7 @@ impl Parse for HandlerSpec {
                 let content;
                 braced!(content in input);
                 let var_ident: Ident = content.parse()?;
-                args.push(var_ident.to_string());
+                args.push(var_ident);
 
                 if input.peek(Token![,]) {
                     input.parse::<Token![,]>()?;
@@ -372,33 +794,158 @@ impl Parse for HandlerSpec {
             Vec::new()
         };
 
-        Ok(HandlerSpec { name, generics })
+        // Runtime fields: (imm, off as i16 * 2)
+        let args = if input.peek(token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            let args: Punctuated<RuntimeArg, Token![,]> =
+                content.parse_terminated(RuntimeArg::parse, Token![,])?;
+            args.into_iter().collect()
+        } else {
+            Vec::new()
+        };
+
+        Ok(HandlerSpec {
+            method,
+            name,
+            generics,
+            args,
+        })
+    }
+}
+
+/// The pattern of an instruction entry.
+///
+/// Either a literal pattern string or a table-level instruction format with some
+/// of its fields fixed to constant values.
+enum PatternSource {
+    /// Bit pattern string (e.g., "0001'00rr")
+    Literal(LitStr),
+    /// Instruction format with fixed fields (e.g., `R(funct3 = 0b000, opcode = 0b0110011)`)
+    Format {
+        /// Name of the format declared with `format Name = "...";`
+        name: Ident,
+        /// Fields fixed to constant values, in declaration order
+        fields: Vec<(Ident, LitInt)>,
+    },
+}
+
+impl Parse for PatternSource {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(LitStr) {
+            let pattern_lit: LitStr = input.parse()?;
+            return Ok(PatternSource::Literal(pattern_lit));
+        }
+
+        // Format(field = value, ...)
+        let name: Ident = input.parse()?;
+        let content;
+        parenthesized!(content in input);
+
+        let mut fields = Vec::new();
+        while !content.is_empty() {
+            let field: Ident = content.parse()?;
+            content.parse::<Token![=]>()?;
+            let value: LitInt = content.parse()?;
+            fields.push((field, value));
+
+            if !content.is_empty() {
+                content.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(PatternSource::Format { name, fields })
+    }
+}
+
+impl PatternSource {
+    /// Span of the pattern string or format name, for errors about the whole pattern.
+    fn span(&self) -> Span {
+        match self {
+            PatternSource::Literal(lit) => lit.span(),
+            PatternSource::Format { name, .. } => name.span(),
+        }
+    }
+
+    /// The pattern as written, for messages referring to an entry.
+    fn describe(&self) -> String {
+        match self {
+            PatternSource::Literal(lit) => format!("\"{}\"", lit.value().trim()),
+            PatternSource::Format { name, fields } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| format!("{} = {}", field, value))
+                    .collect();
+                format!("{}({})", name, fields.join(", "))
+            }
+        }
     }
 }
 
+/// A reusable instruction encoding format.
+///
+/// Syntax: `format R = "{funct7:7}{rs2:5}{rs1:5}{funct3:3}{rd:5}{opcode:7}";`
+struct InstructionFormat {
+    /// Name used by entries to refer to this format (e.g., `R`)
+    name: Ident,
+    /// Bit pattern string of the format, usually made of named fields
+    pattern: LitStr,
+}
+
 /// A single instruction pattern entry.
 ///
 /// Syntax: `"pattern" => handler<...> where { ... };`
 ///
-/// Example: `"0001'00rr" => load<{r}> where { r: Register = { ... } };`
+/// Examples:
+/// - `"0001'00rr" => load<{r}> where { r: Register = { ... } };`
+/// - `R(funct7 = 0b0000000, funct3 = 0b000, opcode = 0b0110011) => add<{rd}>;`
+/// - `#[allow(overlap)] "01__'____" => generic;` - overlaps earlier entries on purpose
 struct InstructionEntry {
-    /// Bit pattern string (e.g., "0001'00rr")
-    pattern: String,
+    /// Whether partial overlaps with earlier entries are intended (`#[allow(overlap)]`)
+    allow_overlap: bool,
+    /// Bit pattern of the entry
+    pattern: PatternSource,
     /// Handler function specification
     handler: HandlerSpec,
+    /// Optional assembly syntax for the disassembler: `as "add {r}, #{imm}"`
+    syntax: Option<LitStr>,
     /// Optional where clause with variable bindings
     where_clause: Option<WhereClause>,
 }
 
 impl Parse for InstructionEntry {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let pattern_lit: LitStr = input.parse()?;
-        let pattern = pattern_lit.value();
+        let mut allow_overlap = false;
+        for attr in input.call(syn::Attribute::parse_outer)? {
+            let lint: Option<Ident> = if attr.path().is_ident("allow") {
+                attr.parse_args().ok()
+            } else {
+                None
+            };
+            match lint {
+                Some(lint) if lint == "overlap" => allow_overlap = true,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "Unsupported attribute, expected `#[allow(overlap)]`",
+                    ));
+                }
+            }
+        }
+
+        let pattern: PatternSource = input.parse()?;
 
         input.parse::<Token![=>]>()?;
 
         let handler: HandlerSpec = input.parse()?;
 
+        let syntax = if input.peek(Token![as]) {
+            input.parse::<Token![as]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
         let where_clause = if input.peek(Token![where]) {
             Some(input.parse()?)
         } else {
@@ -406,510 +953,4308 @@ impl Parse for InstructionEntry {
         };
 
         Ok(InstructionEntry {
+            allow_overlap,
             pattern,
             handler,
+            syntax,
             where_clause,
         })
     }
 }
 
-/// The complete instruction table definition.
+/// The code generation backend used for the dispatcher body.
 ///
-/// Contains all metadata and instruction entries that define the decoder.
-struct InstructionTable {
-    /// The opcode type (u8, u16, u32, or u64) used for pattern matching
-    opcode_type: Type,
-    /// Optional type that handlers receive (converted from opcode_type)
-    handler_opcode_type: Option<Type>,
-    /// Name of the generated dispatcher function
-    dispatcher_name: Ident,
-    /// Type of the context parameter passed to handlers
-    context_type: Type,
-    /// All instruction pattern entries
-    entries: Vec<InstructionEntry>,
+/// Syntax: `strategy = match;`, `strategy = table;`, `strategy = tree;`,
+/// `strategy = two_level(hi = 10);` or `strategy = auto;`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Strategy {
+    /// A single `match` over the opcode with exact and guarded arms (default)
+    Match,
+    /// A static function-pointer table indexed by the opcode (u8/u16 only)
+    Table,
+    /// Nested `match` expressions switching on the most discriminating bits first
+    Tree,
+    /// A first-level table indexed by the top `hi` bits, pointing to handlers or
+    /// deduplicated second-level tables indexed by the remaining bits
+    TwoLevel {
+        /// Number of top opcode bits indexing the first-level table
+        hi: usize,
+    },
+    /// Picks one of the other strategies based on the shape of the table
+    Auto,
 }
 
-impl Parse for InstructionTable {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        // type Opcode = u8;
-        input.parse::<Token![type]>()?;
-        let _: Ident = input.parse()?; // "Opcode"
-        input.parse::<Token![=]>()?;
-        let opcode_type: Type = input.parse()?;
-        input.parse::<Token![;]>()?;
-
-        // Optional: handler_opcode = Instruction;
-        let mut handler_opcode_type = None;
-        let mut dispatcher_name = None;
-        let mut context_type = None;
-
-        // Parse configuration lines in any order
-        while input.peek(Ident) && !input.peek(LitStr) {
-            let ident: Ident = input.parse()?;
-            input.parse::<Token![=]>()?;
+impl std::fmt::Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strategy::Match => write!(f, "match"),
+            Strategy::Table => write!(f, "table"),
+            Strategy::Tree => write!(f, "tree"),
+            Strategy::TwoLevel { hi } => write!(f, "two_level(hi = {})", hi),
+            Strategy::Auto => write!(f, "auto"),
+        }
+    }
+}
 
-            match ident.to_string().as_str() {
-                "handler_opcode" => {
-                    let handler_type: Type = input.parse()?;
-                    handler_opcode_type = Some(handler_type);
-                }
-                "dispatcher" => {
-                    let name: Ident = input.parse()?;
-                    dispatcher_name = Some(name);
-                }
-                "context" => {
-                    let ctx_type: Type = input.parse()?;
-                    context_type = Some(ctx_type);
-                }
-                _ => {
-                    return Err(syn::Error::new(ident.span(), "Unknown configuration key"));
+impl Parse for Strategy {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        // `match` is a keyword, so parse it as any identifier
+        let ident = Ident::parse_any(input)?;
+        match ident.to_string().as_str() {
+            "match" => Ok(Strategy::Match),
+            "table" => Ok(Strategy::Table),
+            "tree" => Ok(Strategy::Tree),
+            "auto" => Ok(Strategy::Auto),
+            "two_level" => {
+                // two_level(hi = 10)
+                let content;
+                parenthesized!(content in input);
+                let key: Ident = content.parse()?;
+                if key != "hi" {
+                    return Err(syn::Error::new(key.span(), "Expected `hi = <bits>`"));
                 }
+                content.parse::<Token![=]>()?;
+                let hi: LitInt = content.parse()?;
+                Ok(Strategy::TwoLevel {
+                    hi: hi.base10_parse()?,
+                })
             }
-            input.parse::<Token![;]>()?;
+            _ => Err(syn::Error::new(
+                ident.span(),
+                "Unknown strategy. Expected `match`, `table`, `tree`, `two_level(hi = N)` or `auto`",
+            )),
         }
+    }
+}
 
-        let dispatcher_name = dispatcher_name
-            .ok_or_else(|| syn::Error::new(input.span(), "Missing 'dispatcher' configuration"))?;
-        let context_type = context_type
-            .ok_or_else(|| syn::Error::new(input.span(), "Missing 'context' configuration"))?;
+/// How the opcode coverage of the table is checked.
+///
+/// Syntax: `coverage = report;` or `coverage = require;`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Coverage {
+    /// Emit the unhandled opcodes and the coverage percentage as constants
+    Report,
+    /// Like `Report`, but fail compilation unless every opcode is handled
+    Require,
+}
 
-        // Parse instruction entries
-        let mut entries = Vec::new();
-        while !input.is_empty() {
-            entries.push(input.parse()?);
-            input.parse::<Token![;]>()?;
+impl Parse for Coverage {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+        match ident.to_string().as_str() {
+            "report" => Ok(Coverage::Report),
+            "require" => Ok(Coverage::Require),
+            _ => Err(syn::Error::new(
+                ident.span(),
+                "Unknown coverage mode. Expected `report` or `require`",
+            )),
         }
-
-        Ok(InstructionTable {
-            opcode_type,
-            handler_opcode_type,
-            dispatcher_name,
-            context_type,
-            entries,
-        })
     }
 }
 
-/// A parsed and analyzed bit pattern.
+/// Which entry wins when several patterns match the same opcode.
 ///
-/// After parsing a pattern string like `"0001'00rr"`, this contains:
-/// - Which bits are fixed (mask/value)
-/// - Which bits are variables (variables map)
-/// - Which bits are wildcards (wildcard_bits)
-#[derive(Debug, Clone)]
-struct ParsedPattern {
-    /// Mask where 1 = fixed bit, 0 = variable or wildcard
-    mask: u64,
-    /// Expected values for fixed bits (where mask = 1)
-    value: u64,
-    /// Variables extracted from pattern: var_name -> (bit_position, num_bits)
-    /// e.g., "rr" from "0001'00rr" -> ("r", (0, 2))
-    variables: HashMap<String, (u8, u8)>,
-    /// Bitmask of wildcard positions (1 = wildcard bit)
-    wildcard_bits: u64,
-    /// Total bit width (8, 16, 32, or 64)
-    bit_width: usize,
+/// Syntax: `priority = declaration;` or `priority = specificity;`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Priority {
+    /// The first matching entry in declaration order wins
+    Declaration,
+    /// The matching entry with the most fixed bits wins
+    Specificity,
 }
 
-/// Parses a pattern string into a structured representation.
+impl Parse for Priority {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+        match ident.to_string().as_str() {
+            "declaration" => Ok(Priority::Declaration),
+            "specificity" => Ok(Priority::Specificity),
+            _ => Err(syn::Error::new(
+                ident.span(),
+                "Unknown priority. Expected `declaration` or `specificity`",
+            )),
+        }
+    }
+}
+
+/// What the dispatcher does with opcodes that no entry matches, instead of panicking.
 ///
-/// Pattern syntax:
-/// - '0', '1': Fixed bits
-/// - 'a-z': Variable bits (consecutive same letters form a multi-bit variable)
-/// - '_', '.': Wildcard bits (don't care)
+/// Syntax: `fallback = handler;`, `fallback = self.method;` or `fallback = <value>;`. A
+/// path is always taken as a handler, constants are written in braces:
+/// `fallback = { HALT };`
+#[derive(Clone)]
+enum Fallback {
+    /// Call a handler with the same signature as every other handler
+    Handler {
+        /// Whether the handler is a method called on the first parameter
+        method: bool,
+        /// Handler function name
+        name: Path,
+    },
+    /// Return a value of the `returns` type
+    Value(Expr),
+}
+
+impl Parse for Fallback {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![self]) && input.peek2(Token![.]) {
+            input.parse::<Token![self]>()?;
+            input.parse::<Token![.]>()?;
+            let name: Ident = input.parse()?;
+            return Ok(Fallback::Handler {
+                method: true,
+                name: name.into(),
+            });
+        }
+
+        match input.parse()? {
+            Expr::Path(path) if path.attrs.is_empty() && path.qself.is_none() => {
+                Ok(Fallback::Handler {
+                    method: false,
+                    name: path.path,
+                })
+            }
+            value => Ok(Fallback::Value(value)),
+        }
+    }
+}
+
+/// How handlers are called.
+///
+/// Syntax: `handlers = function;` or `handlers = method;`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Handlers {
+    /// Free functions taking every parameter: `add(ctx, opcode)`
+    Function,
+    /// Methods of the first parameter: `ctx.add(opcode)`
+    Method,
+}
+
+impl Parse for Handlers {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+        match ident.to_string().as_str() {
+            "function" => Ok(Handlers::Function),
+            "method" => Ok(Handlers::Method),
+            _ => Err(syn::Error::new(
+                ident.span(),
+                "Unknown handler kind. Expected `function` or `method`",
+            )),
+        }
+    }
+}
+
+/// A parameter of the dispatcher, forwarded to every handler in order.
+#[derive(Clone)]
+enum SignatureParam {
+    /// A parameter passed through unchanged, like the context
+    Forwarded {
+        /// Name of the parameter in the dispatcher
+        name: Ident,
+        /// Type of the parameter, e.g. `&mut Cpu`
+        ty: Box<Type>,
+    },
+    /// The opcode, converted to `handler_opcode` if set
+    Opcode,
+}
+
+/// The parameters and return type shared by the dispatcher and every handler.
+///
+/// Syntax: `signature = fn(cpu: &mut Cpu, bus: &mut impl Bus, opcode) -> u32;`, where
+/// `opcode` marks the position of the opcode. `context = Cpu;` is short for
+/// `signature = fn(ctx: &mut Cpu, opcode);`. Generics are declared on the dispatcher:
+/// `dispatcher = dispatch<B: Bus>;`
+#[derive(Clone)]
+struct Signature {
+    /// Generic parameters and where clause of the dispatcher
+    generics: Generics,
+    /// Parameters in the order they are passed to handlers
+    params: Vec<SignatureParam>,
+    /// Type returned by every handler and the dispatcher, `()` if not set
+    output: Option<Type>,
+}
+
+impl Parse for Signature {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        input.parse::<Token![fn]>()?;
+        let content;
+        let parens = parenthesized!(content in input);
+
+        let mut params = Vec::new();
+        let mut has_opcode = false;
+        while !content.is_empty() {
+            let name: Ident = content.parse()?;
+            if content.peek(Token![:]) {
+                if name == "opcode" {
+                    return Err(syn::Error::new(
+                        name.span(),
+                        "`opcode` is the opcode parameter and can't have a type",
+                    ));
+                }
+                content.parse::<Token![:]>()?;
+                let ty: Box<Type> = content.parse()?;
+                params.push(SignatureParam::Forwarded { name, ty });
+            } else if name == "opcode" && !has_opcode {
+                params.push(SignatureParam::Opcode);
+                has_opcode = true;
+            } else if name == "opcode" {
+                return Err(syn::Error::new(name.span(), "Duplicate `opcode` parameter"));
+            } else {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "Expected `name: Type`, or `opcode` for the opcode",
+                ));
+            }
+
+            if content.is_empty() {
+                break;
+            }
+            content.parse::<Token![,]>()?;
+        }
+        if !has_opcode {
+            return Err(syn::Error::new(
+                parens.span.join(),
+                "The signature must have an `opcode` parameter",
+            ));
+        }
+
+        let output = if input.peek(Token![->]) {
+            input.parse::<Token![->]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
+        Ok(Signature {
+            generics: Generics::default(),
+            params,
+            output,
+        })
+    }
+}
+
+impl Signature {
+    /// The signature of `context = Cpu;`: `fn(ctx: &mut Cpu, opcode)`
+    fn context(context_type: Type) -> Self {
+        Signature {
+            generics: Generics::default(),
+            params: vec![
+                SignatureParam::Forwarded {
+                    name: format_ident!("ctx"),
+                    ty: syn::parse_quote! { &mut #context_type },
+                },
+                SignatureParam::Opcode,
+            ],
+            output: None,
+        }
+    }
+
+    /// The arguments of a call forwarding every parameter, passing `opcode` as the
+    /// opcode
+    fn args(&self, opcode: &TokenStream2) -> Vec<TokenStream2> {
+        self.params
+            .iter()
+            .map(|param| match param {
+                SignatureParam::Forwarded { name, .. } => quote! { #name },
+                SignatureParam::Opcode => opcode.clone(),
+            })
+            .collect()
+    }
+
+    /// The parameters of a function with this signature, taking the opcode as
+    /// `opcode_type`
+    fn inputs(&self, opcode_type: &TokenStream2) -> Vec<TokenStream2> {
+        self.params
+            .iter()
+            .map(|param| match param {
+                SignatureParam::Forwarded { name, ty } => quote! { #name: #ty },
+                SignatureParam::Opcode => quote! { opcode: #opcode_type },
+            })
+            .collect()
+    }
+
+    /// The return type as `-> R`, empty for `()`
+    fn output(&self) -> TokenStream2 {
+        match &self.output {
+            Some(output) => quote! { -> #output },
+            None => TokenStream2::new(),
+        }
+    }
+
+    /// The generic arguments forwarded to every handler: the type and const parameters
+    /// of the dispatcher. Lifetimes are left to inference.
+    fn generic_args(&self) -> Vec<TokenStream2> {
+        self.generics
+            .params
+            .iter()
+            .filter_map(|param| match param {
+                GenericParam::Type(param) => Some(&param.ident),
+                GenericParam::Const(param) => Some(&param.ident),
+                GenericParam::Lifetime(_) => None,
+            })
+            .map(|ident| quote! { #ident })
+            .collect()
+    }
+
+    /// Whether the first parameter can receive method calls, which the opcode can't
+    fn has_receiver(&self) -> bool {
+        matches!(self.params.first(), Some(SignatureParam::Forwarded { .. }))
+    }
+
+    /// The function pointer type of handlers taking the opcode as `opcode_type`
+    fn fn_pointer(&self, opcode_type: &Type) -> TokenStream2 {
+        let types = self.params.iter().map(|param| match param {
+            SignatureParam::Forwarded { ty, .. } => quote! { #ty },
+            SignatureParam::Opcode => quote! { #opcode_type },
+        });
+        let output = self.output();
+        quote! { fn(#(#types),*) #output }
+    }
+
+    /// Whether the dispatcher has generic parameters or `impl Trait` parameters, which
+    /// rules out function pointer tables
+    fn is_generic(&self) -> bool {
+        fn contains_impl(tokens: TokenStream2) -> bool {
+            tokens.into_iter().any(|token| match token {
+                proc_macro2::TokenTree::Ident(ident) => ident == "impl",
+                proc_macro2::TokenTree::Group(group) => contains_impl(group.stream()),
+                _ => false,
+            })
+        }
+        !self.generics.params.is_empty()
+            || self.params.iter().any(|param| match param {
+                SignatureParam::Forwarded { ty, .. } => contains_impl(quote! { #ty }),
+                SignatureParam::Opcode => false,
+            })
+    }
+}
+
+/// A dispatcher function generated from the table.
+///
+/// Syntax: `dispatcher = dispatch;`, or `dispatcher exec = execute in exec_ops;` for one
+/// of several dispatchers, labelled `exec`, whose handlers are resolved relative to
+/// the module `exec_ops`.
+struct Dispatcher {
+    /// Label of the dispatcher, `None` for the unlabelled one
+    label: Option<Ident>,
+    /// Name of the generated dispatcher function
+    name: Ident,
+    /// Module that handler paths are relative to
+    module: Option<Path>,
+    /// Parameters and return type of the dispatcher and every handler
+    signature: Signature,
+    /// Whether handlers are free functions or methods of the first parameter
+    handlers: Handlers,
+    /// Optional trait declaring every handler as a method, generated next to the
+    /// dispatcher
+    handler_trait: Option<Ident>,
+    /// Optional handling of opcodes that no entry matches, instead of panicking
+    fallback: Option<Fallback>,
+    /// Whether a `try_<dispatcher>` returning unhandled opcodes as errors is generated
+    try_dispatcher: bool,
+    /// Code generation backend for the dispatcher body
+    strategy: Strategy,
+}
+
+impl Dispatcher {
+    /// Writes a setting of this dispatcher as it would appear in the table, e.g.
+    /// `strategy exec = tree;`.
+    fn setting(&self, key: &str, value: &str) -> String {
+        match &self.label {
+            Some(label) => format!("{} {} = {};", key, label, value),
+            None => format!("{} = {};", key, value),
+        }
+    }
+}
+
+/// The settings of a dispatcher, as written in the table.
+///
+/// Unlabelled settings (`context = Cpu;`) apply to every dispatcher, labelled settings
+/// (`context exec = Cpu;`) to the dispatcher with that label, replacing the unlabelled
+/// setting.
+#[derive(Default)]
+struct DispatcherSettings {
+    /// Label of the dispatcher the settings apply to, `None` for every dispatcher
+    label: Option<Ident>,
+    /// Type of the context, `context = Cpu;`
+    context: Option<Type>,
+    /// Full signature with the span of the setting, `signature = fn(...);`
+    signature: Option<(Signature, Span)>,
+    /// Return type of the handlers, `returns = u32;`
+    returns: Option<Type>,
+    /// How handlers are called, with the span of the setting
+    handlers: Option<(Handlers, Span)>,
+    /// Name of the generated handler trait, `handler_trait = CpuHandlers;`
+    handler_trait: Option<Ident>,
+    /// Handling of unhandled opcodes, `fallback = illegal;`
+    fallback: Option<Fallback>,
+    /// Whether a `try_<dispatcher>` is generated, `try_dispatcher = true;`
+    try_dispatcher: Option<bool>,
+    /// Code generation backend, `strategy = table;`
+    strategy: Option<Strategy>,
+}
+
+impl DispatcherSettings {
+    /// Combines the settings of a dispatcher with the unlabelled `defaults` into its
+    /// signature and handler configuration. `context` and `signature` replace each
+    /// other, so either both or none of them are taken from the defaults.
+    fn resolve(
+        &self,
+        defaults: &DispatcherSettings,
+        name: Ident,
+        generics: Generics,
+        module: Option<Path>,
+        span: Span,
+    ) -> syn::Result<Dispatcher> {
+        let own = self.context.is_some() || self.signature.is_some();
+        let (context_type, signature) = if own {
+            (&self.context, &self.signature)
+        } else {
+            (&defaults.context, &defaults.signature)
+        };
+        let handler_trait = self
+            .handler_trait
+            .as_ref()
+            .or(defaults.handler_trait.as_ref());
+
+        let mut signature = match (context_type, signature) {
+            (Some(context_type), None) => Signature::context(context_type.clone()),
+            (None, Some((signature, _))) => signature.clone(),
+            (Some(_), Some((_, span))) => {
+                return Err(syn::Error::new(
+                    *span,
+                    "`signature` replaces `context`, pass the context as a parameter instead",
+                ));
+            }
+            // The context is any implementation of the handler trait
+            (None, None) => match handler_trait {
+                Some(trait_name) => {
+                    let (_, ty_generics, _) = generics.split_for_impl();
+                    Signature::context(syn::parse_quote! { impl #trait_name #ty_generics })
+                }
+                None => {
+                    let message = match &self.label {
+                        Some(label) => format!(
+                            "Missing 'context' or 'signature' configuration for dispatcher '{}'",
+                            label
+                        ),
+                        None => "Missing 'context' or 'signature' configuration".to_string(),
+                    };
+                    return Err(syn::Error::new(span, message));
+                }
+            },
+        };
+        if let Some(returns) = self.returns.as_ref().or(defaults.returns.as_ref()) {
+            if signature.output.is_some() {
+                return Err(syn::Error::new_spanned(
+                    returns,
+                    "The return type is already declared by `signature`",
+                ));
+            }
+            signature.output = Some(returns.clone());
+        }
+        signature.generics = generics;
+
+        // The handlers of a trait are its methods
+        let handlers = match (self.handlers.or(defaults.handlers), handler_trait) {
+            (Some((Handlers::Function, span)), Some(_)) => {
+                return Err(syn::Error::new(
+                    span,
+                    "Handlers of a `handler_trait` are its methods, remove `handlers = function`",
+                ));
+            }
+            (_, Some(_)) => Handlers::Method,
+            (handlers, None) => handlers.map_or(Handlers::Function, |(handlers, _)| handlers),
+        };
+
+        Ok(Dispatcher {
+            label: self.label.clone(),
+            name,
+            module,
+            signature,
+            handlers,
+            handler_trait: handler_trait.cloned(),
+            fallback: self.fallback.clone().or_else(|| defaults.fallback.clone()),
+            try_dispatcher: self
+                .try_dispatcher
+                .or(defaults.try_dispatcher)
+                .unwrap_or(false),
+            strategy: self
+                .strategy
+                .or(defaults.strategy)
+                .unwrap_or(Strategy::Match),
+        })
+    }
+}
+
+/// A decoder turning opcodes into values of a generated instruction enum instead of
+/// calling handlers.
+///
+/// Syntax: `decoder = decode -> Instruction;`, and `encoder = encode;` for the inverse
+struct Decoder {
+    /// Name of the generated decoder function
+    name: Ident,
+    /// Name of the generated instruction enum
+    instruction: Ident,
+    /// Name of the generated method encoding instructions, if any
+    encoder: Option<Ident>,
+}
+
+impl Parse for Decoder {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name: Ident = input.parse()?;
+        input.parse::<Token![->]>()?;
+        let instruction: Ident = input.parse()?;
+        Ok(Decoder {
+            name,
+            instruction,
+            encoder: None,
+        })
+    }
+}
+
+impl Decoder {
+    /// The decoder as a dispatcher that only takes the opcode and returns
+    /// `Option<Instruction>`, `None` for opcodes that no entry matches. It uses the
+    /// unlabelled `strategy`, and its strategy report is named after it, e.g.
+    /// `DECODE_STRATEGY_REPORT`.
+    fn dispatcher(&self, strategy: Strategy) -> Dispatcher {
+        let instruction = &self.instruction;
+        Dispatcher {
+            label: Some(self.name.clone()),
+            name: self.name.clone(),
+            module: None,
+            signature: Signature {
+                generics: Generics::default(),
+                params: vec![SignatureParam::Opcode],
+                output: Some(syn::parse_quote! { ::core::option::Option<#instruction> }),
+            },
+            handlers: Handlers::Function,
+            handler_trait: None,
+            fallback: Some(Fallback::Value(
+                syn::parse_quote! { ::core::option::Option::None },
+            )),
+            try_dispatcher: false,
+            strategy,
+        }
+    }
+}
+
+/// A disassembler writing the assembly syntax of the entries, generated when entries
+/// have syntax strings (`as "add {r}, #{imm}"`).
+///
+/// Syntax: `disassembler = disassemble;` to name it, `disassemble` by default
+struct Disassembler {
+    /// Name of the generated disassembler function
+    name: Ident,
+}
+
+impl Disassembler {
+    /// The disassembler as a dispatcher taking the opcode and the output, and
+    /// returning a `fmt::Result`. Opcodes that no entry matches are written as `(bad)`.
+    /// It uses the unlabelled `strategy`, and its strategy report is named after it,
+    /// e.g. `DISASSEMBLE_STRATEGY_REPORT`. The output is generic, which static tables
+    /// can't store handlers for, so `table` and `two_level` disassemble with a `match`.
+    fn dispatcher(&self, strategy: Strategy) -> Dispatcher {
+        let strategy = match strategy {
+            Strategy::Table | Strategy::TwoLevel { .. } => Strategy::Match,
+            strategy => strategy,
+        };
+        Dispatcher {
+            label: Some(self.name.clone()),
+            name: self.name.clone(),
+            module: None,
+            signature: Signature {
+                generics: Generics::default(),
+                params: vec![
+                    SignatureParam::Opcode,
+                    SignatureParam::Forwarded {
+                        name: format_ident!("out"),
+                        ty: syn::parse_quote! { &mut impl ::core::fmt::Write },
+                    },
+                ],
+                output: Some(syn::parse_quote! { ::core::fmt::Result }),
+            },
+            handlers: Handlers::Function,
+            handler_trait: None,
+            fallback: Some(Fallback::Value(
+                syn::parse_quote! { out.write_str("(bad)") },
+            )),
+            try_dispatcher: false,
+            strategy,
+        }
+    }
+}
+
+/// The complete instruction table definition.
+///
+/// Contains all metadata and instruction entries that define the decoder.
+struct InstructionTable {
+    /// The opcode type (u8, u16, u32, or u64) used for pattern matching
+    opcode_type: Type,
+    /// Optional type that handlers receive (converted from opcode_type)
+    handler_opcode_type: Option<Type>,
+    /// Dispatchers generated from the table, in declaration order
+    dispatchers: Vec<Dispatcher>,
+    /// Optional decoder returning the instruction enum
+    decoder: Option<Decoder>,
+    /// Disassembler, if entries have syntax strings
+    disassembler: Option<Disassembler>,
+    /// Code generation backend of the decoder and disassembler, the unlabelled
+    /// `strategy`
+    strategy: Strategy,
+    /// Optional coverage check, with the span of the mode for errors
+    coverage: Option<(Coverage, Span)>,
+    /// Order in which overlapping entries are matched
+    priority: Priority,
+    /// Instruction formats that entries can refer to
+    formats: Vec<InstructionFormat>,
+    /// All instruction pattern entries
+    entries: Vec<InstructionEntry>,
+}
+
+impl Parse for InstructionTable {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        // type Opcode = u8;
+        input.parse::<Token![type]>()?;
+        let _: Ident = input.parse()?; // "Opcode"
+        input.parse::<Token![=]>()?;
+        let opcode_type: Type = input.parse()?;
+        input.parse::<Token![;]>()?;
+
+        // Optional: handler_opcode = Instruction;
+        let mut handler_opcode_type = None;
+        let mut declarations: Vec<(Option<Ident>, Ident, Generics, Option<Path>)> = Vec::new();
+        let mut settings: Vec<DispatcherSettings> = vec![DispatcherSettings::default()];
+        let mut decoder: Option<Decoder> = None;
+        let mut encoder = None;
+        let mut disassembler = None;
+        let mut coverage = None;
+        let mut priority = Priority::Declaration;
+        let mut formats = Vec::new();
+
+        // Parse configuration lines in any order. Entries using a format also start
+        // with an identifier, but are followed by parentheses instead.
+        while input.peek(Ident) && (input.peek2(Token![=]) || input.peek2(Ident)) {
+            let ident: Ident = input.parse()?;
+
+            if ident == "format" {
+                // format R = "...";
+                let name: Ident = input.parse()?;
+                input.parse::<Token![=]>()?;
+                let pattern: LitStr = input.parse()?;
+                formats.push(InstructionFormat { name, pattern });
+                input.parse::<Token![;]>()?;
+                continue;
+            }
+
+            // Settings of a single dispatcher: `context exec = Cpu;`
+            let label: Option<Ident> = if input.peek(Ident) {
+                Some(input.parse()?)
+            } else {
+                None
+            };
+            input.parse::<Token![=]>()?;
+
+            let key = ident.to_string();
+            if let Some(label) = &label
+                && matches!(
+                    key.as_str(),
+                    "handler_opcode"
+                        | "coverage"
+                        | "priority"
+                        | "decoder"
+                        | "encoder"
+                        | "disassembler"
+                )
+            {
+                return Err(syn::Error::new(
+                    label.span(),
+                    format!(
+                        "`{}` is shared by every dispatcher and can't be labelled",
+                        key
+                    ),
+                ));
+            }
+            let index = match settings.iter().position(|s| s.label == label) {
+                Some(index) => index,
+                None => {
+                    settings.push(DispatcherSettings {
+                        label: label.clone(),
+                        ..Default::default()
+                    });
+                    settings.len() - 1
+                }
+            };
+            let dispatcher = &mut settings[index];
+
+            match key.as_str() {
+                "handler_opcode" => {
+                    let handler_type: Type = input.parse()?;
+                    handler_opcode_type = Some(handler_type);
+                }
+                "dispatcher" => {
+                    // dispatch<B: Bus> in ops where B: Clone
+                    let name: Ident = input.parse()?;
+                    let mut generics: Generics = input.parse()?;
+                    let module = if input.peek(Token![in]) {
+                        input.parse::<Token![in]>()?;
+                        Some(input.call(Path::parse_mod_style)?)
+                    } else {
+                        None
+                    };
+                    generics.where_clause = input.parse()?;
+                    if declarations.iter().any(|(other, ..)| *other == label) {
+                        let message = match &label {
+                            Some(label) => format!("Duplicate dispatcher label '{}'", label),
+                            None => "Duplicate unlabelled dispatcher, label each dispatcher: \
+                                     `dispatcher exec = execute;`"
+                                .to_string(),
+                        };
+                        return Err(syn::Error::new(
+                            label.as_ref().map_or(name.span(), Ident::span),
+                            message,
+                        ));
+                    }
+                    declarations.push((label, name, generics, module));
+                }
+                "decoder" => {
+                    decoder = Some(input.parse()?);
+                }
+                "encoder" => {
+                    encoder = Some(input.parse::<Ident>()?);
+                }
+                "disassembler" => {
+                    let name: Ident = input.parse()?;
+                    disassembler = Some(Disassembler { name });
+                }
+                "context" => {
+                    let ctx_type: Type = input.parse()?;
+                    dispatcher.context = Some(ctx_type);
+                }
+                "signature" => {
+                    let span = input.span();
+                    dispatcher.signature = Some((input.parse::<Signature>()?, span));
+                }
+                "strategy" => {
+                    dispatcher.strategy = Some(input.parse()?);
+                }
+                "coverage" => {
+                    let span = input.span();
+                    coverage = Some((input.parse()?, span));
+                }
+                "priority" => {
+                    priority = input.parse()?;
+                }
+                "returns" => {
+                    let return_type: Type = input.parse()?;
+                    dispatcher.returns = Some(return_type);
+                }
+                "handlers" => {
+                    let span = input.span();
+                    dispatcher.handlers = Some((input.parse()?, span));
+                }
+                "handler_trait" => {
+                    let name: Ident = input.parse()?;
+                    dispatcher.handler_trait = Some(name);
+                }
+                "fallback" => {
+                    dispatcher.fallback = Some(input.parse()?);
+                }
+                "try_dispatcher" => {
+                    let enabled: LitBool = input.parse()?;
+                    dispatcher.try_dispatcher = Some(enabled.value);
+                }
+                _ => {
+                    return Err(syn::Error::new(ident.span(), "Unknown configuration key"));
+                }
+            }
+            input.parse::<Token![;]>()?;
+        }
+
+        // Parse instruction entries
+        let entries_span = input.span();
+        let mut entries: Vec<InstructionEntry> = Vec::new();
+        while !input.is_empty() {
+            entries.push(input.parse()?);
+            input.parse::<Token![;]>()?;
+        }
+
+        // The encoder is a method of the decoder's instruction enum
+        if let Some(encoder) = encoder {
+            match &mut decoder {
+                Some(decoder) => decoder.encoder = Some(encoder),
+                None => {
+                    return Err(syn::Error::new(
+                        encoder.span(),
+                        "The encoder is a method of the instruction enum, add a 'decoder' \
+                         configuration: `decoder = decode -> Instruction;`",
+                    ));
+                }
+            }
+        }
+
+        // Syntax strings imply a disassembler
+        if disassembler.is_none() && entries.iter().any(|entry| entry.syntax.is_some()) {
+            disassembler = Some(Disassembler {
+                name: format_ident!("disassemble"),
+            });
+        }
+
+        if declarations.is_empty() && decoder.is_none() && disassembler.is_none() {
+            return Err(syn::Error::new(
+                entries_span,
+                "Missing 'dispatcher', 'decoder' or 'disassembler' configuration",
+            ));
+        }
+        for labelled in &settings[1..] {
+            if let Some(label) = &labelled.label
+                && !declarations
+                    .iter()
+                    .any(|(other, ..)| other.as_ref() == Some(label))
+            {
+                return Err(syn::Error::new(
+                    label.span(),
+                    format!("No dispatcher is labelled '{}'", label),
+                ));
+            }
+        }
+        let defaults = &settings[0];
+        let strategy = defaults.strategy.unwrap_or(Strategy::Match);
+        let dispatchers = declarations
+            .into_iter()
+            .map(|(label, name, generics, module)| {
+                // Declaring a dispatcher adds settings for its label
+                let own = settings
+                    .iter()
+                    .find(|s| s.label == label)
+                    .unwrap_or(defaults);
+                // Errors about a labelled dispatcher point at its name
+                let span = match label {
+                    Some(_) => name.span(),
+                    None => entries_span,
+                };
+                own.resolve(defaults, name, generics, module, span)
+            })
+            .collect::<syn::Result<Vec<Dispatcher>>>()?;
+
+        Ok(InstructionTable {
+            opcode_type,
+            handler_opcode_type,
+            dispatchers,
+            decoder,
+            disassembler,
+            strategy,
+            coverage,
+            priority,
+            formats,
+            entries,
+        })
+    }
+}
+
+/// A parsed and analyzed bit pattern.
+///
+/// After parsing a pattern string like `"0001'00rr"`, this contains:
+/// - Which bits are fixed (mask/value)
+/// - Which bits are variables (variables map)
+/// - Everything else is a wildcard
+#[derive(Debug, Clone)]
+struct ParsedPattern {
+    /// Mask where 1 = fixed bit, 0 = variable or wildcard
+    mask: u64,
+    /// Expected values for fixed bits (where mask = 1)
+    value: u64,
+    /// Variables extracted from pattern: var_name -> bit positions, MSB first
+    /// e.g., "rr" from "0001'00rr" -> ("r", [1, 0])
+    /// and "i" from "i000'0iii" -> ("i", [7, 2, 1, 0])
+    variables: HashMap<String, Vec<u8>>,
+    /// Total bit width (8, 16, 32, or 64)
+    bit_width: usize,
+}
+
+/// A single bit of a pattern string.
+enum PatternBit {
+    /// Fixed bit that must match exactly
+    Fixed(bool),
+    /// Don't care bit
+    Wildcard,
+    /// Bit of the named variable
+    Variable(String),
+}
+
+/// Returns the span of a byte range of a pattern string literal.
+///
+/// Falls back to the span of the whole literal when the range can't be mapped onto
+/// the source, e.g. for strings with escapes or when the compiler doesn't support
+/// literal subspans.
+fn pattern_span(lit: &LitStr, range: std::ops::Range<usize>) -> Span {
+    let token = lit.token();
+    let source = token.to_string();
+    let value = lit.value();
+
+    // Skip the opening quote of "..." or r#"..."#
+    let prefix = match source.strip_prefix('r') {
+        Some(raw) => 2 + raw.chars().take_while(|&c| c == '#').count(),
+        None => 1,
+    };
+    if source.get(prefix..prefix + value.len()) != Some(value.as_str()) {
+        return lit.span();
+    }
+
+    token
+        .subspan(prefix + range.start..prefix + range.end)
+        .unwrap_or_else(|| lit.span())
+}
+
+/// Splits a pattern string into its bits, MSB first.
+///
+/// Named fields like `{rd:5}` are expanded into one bit per width, separators are
+/// dropped. Every invalid character or field is reported with its own span.
+fn pattern_bits(lit: &LitStr) -> syn::Result<Vec<PatternBit>> {
+    let pattern = lit.value();
+    let mut bits = Vec::new();
+    let mut errors = Errors::default();
+
+    // Surrounding whitespace is ignored
+    let start = pattern.len() - pattern.trim_start().len();
+    let end = pattern.trim_end().len();
+    let mut index = start;
+
+    while index < end {
+        let ch = pattern[index..].chars().next().unwrap_or_default();
+        let char_end = index + ch.len_utf8();
+
+        match ch {
+            '\'' => {
+                // Visual separator
+            }
+            '0' | '1' => bits.push(PatternBit::Fixed(ch == '1')),
+            '_' | '.' => bits.push(PatternBit::Wildcard),
+            c if c.is_ascii_lowercase() => bits.push(PatternBit::Variable(c.to_string())),
+            '{' => {
+                // Named field: {name:width}
+                let Some(close) = pattern[index..end].find('}').map(|close| index + close) else {
+                    errors.push(syn::Error::new(
+                        pattern_span(lit, index..end),
+                        "Unclosed named field, expected {name:width}",
+                    ));
+                    break;
+                };
+                let span = pattern_span(lit, index..close + 1);
+                let field = &pattern[index + 1..close];
+                index = close + 1;
+
+                let Some((name, width)) = field.split_once(':') else {
+                    errors.push(syn::Error::new(
+                        span,
+                        format!(
+                            "Named field '{{{}}}' must be written as {{name:width}}",
+                            field
+                        ),
+                    ));
+                    continue;
+                };
+                let name = name.trim();
+                let valid_name = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid_name {
+                    errors.push(syn::Error::new(
+                        span,
+                        format!("Invalid field name '{}' in pattern", name),
+                    ));
+                    continue;
+                }
+                let Some(width) = width.trim().parse::<usize>().ok().filter(|&width| width > 0) else {
+                    errors.push(syn::Error::new(
+                        span,
+                        format!("Invalid width for field '{}' in pattern", name),
+                    ));
+                    continue;
+                };
+                // Checked before pushing its bits, `{imm:4000000000}` would exhaust memory
+                if width > 64 {
+                    errors.push(syn::Error::new(
+                        span,
+                        format!(
+                            "Field '{}' is {} bits wide, but opcodes have at most 64 bits",
+                            name, width
+                        ),
+                    ));
+                    continue;
+                }
+                for _ in 0..width {
+                    bits.push(PatternBit::Variable(name.to_string()));
+                }
+                continue;
+            }
+            _ => errors.push(syn::Error::new(
+                pattern_span(lit, index..char_end),
+                format!(
+                    "Invalid pattern character: '{}'. Use 0/1 for fixed bits, a-z or {{name:width}} for variables, _ or . for wildcards, ' for visual separation",
+                    ch
+                ),
+            )),
+        }
+
+        index = char_end;
+    }
+
+    errors.finish()?;
+    Ok(bits)
+}
+
+/// Parses a pattern string into a structured representation.
+///
+/// Pattern syntax:
+/// - '0', '1': Fixed bits
+/// - 'a-z': Variable bits (all bits with the same letter form one variable, gathered
+///   MSB first, even if they are not contiguous)
+/// - '{name:width}': Named variable with an explicit width (e.g., `{rs1:5}`), same
+///   rules as single letters
+/// - '_', '.': Wildcard bits (don't care)
 /// - '\'': Visual separator (ignored)
 ///
-/// # Examples
-/// - `"00011000"` -> fixed pattern (mask=0xFF, value=0x18)
-/// - `"0001'00rr"` -> pattern with 2-bit variable 'r' at position 0-1
-/// - `"11rr'____"` -> pattern with variable 'r' and 4 wildcard bits
-fn parse_pattern(pattern: &str) -> ParsedPattern {
-    let pattern = pattern.trim();
+/// # Examples
+/// - `"00011000"` -> fixed pattern (mask=0xFF, value=0x18)
+/// - `"0001'00rr"` -> pattern with 2-bit variable 'r' at position 0-1
+/// - `"11rr'____"` -> pattern with variable 'r' and 4 wildcard bits
+/// - `"i000'0iii"` -> pattern with 4-bit variable 'i' at positions 7, 2, 1 and 0
+/// - `"0000'{rd:4}"` -> pattern with 4-bit variable 'rd' at positions 0-3
+fn parse_pattern(pattern: &LitStr) -> syn::Result<ParsedPattern> {
+    let bits = pattern_bits(pattern)?;
+    let bit_width = bits.len();
+
+    if !matches!(bit_width, 8 | 16 | 32 | 64) {
+        return Err(syn::Error::new(
+            pattern.span(),
+            format!(
+                "Pattern must be exactly 8, 16, 32, or 64 bits. Got {} bits: {}",
+                bit_width,
+                pattern.value().trim()
+            ),
+        ));
+    }
+
+    let mut mask = 0u64;
+    let mut value = 0u64;
+    let mut variables: HashMap<String, Vec<u8>> = HashMap::new();
+
+    for (i, bit) in bits.into_iter().enumerate() {
+        let bit_pos = (bit_width - 1 - i) as u8; // MSB first
+
+        match bit {
+            PatternBit::Fixed(set) => {
+                mask |= 1 << bit_pos;
+                if set {
+                    value |= 1 << bit_pos;
+                }
+            }
+            PatternBit::Wildcard => {
+                // Wildcard - don't care bit (not in mask, not a variable)
+            }
+            PatternBit::Variable(name) => {
+                // Positions are collected MSB first, which is the order the bits of a
+                // value are gathered in, even when the variable is split
+                variables.entry(name).or_default().push(bit_pos);
+            }
+        }
+    }
+
+    Ok(ParsedPattern {
+        mask,
+        value,
+        variables,
+        bit_width,
+    })
+}
+
+/// Resolves the pattern of an entry, expanding instruction formats.
+///
+/// For formats, the format pattern is parsed and every fixed field is turned from a
+/// variable into fixed bits, resulting in the same `ParsedPattern` as if the entry
+/// had spelled out the full pattern string.
+///
+/// `formats` holds the parsed pattern of every declared format, `None` for formats
+/// whose pattern is invalid. Entries using such a format resolve to `Ok(None)`,
+/// since the error has already been reported for the format itself.
+fn resolve_pattern(
+    source: &PatternSource,
+    formats: &[(&Ident, Option<ParsedPattern>)],
+) -> syn::Result<Option<ParsedPattern>> {
+    let (name, fields) = match source {
+        PatternSource::Literal(pattern) => return parse_pattern(pattern).map(Some),
+        PatternSource::Format { name, fields } => (name, fields),
+    };
+
+    let format = formats
+        .iter()
+        .find(|(format_name, _)| *format_name == name)
+        .ok_or_else(|| {
+            syn::Error::new(
+                name.span(),
+                format!("Unknown instruction format '{}'", name),
+            )
+        })?;
+    let Some(format_pattern) = &format.1 else {
+        return Ok(None);
+    };
+
+    let mut pattern = format_pattern.clone();
+    let mut errors = Errors::default();
+
+    for (field, value_lit) in fields {
+        let field_name = field.to_string();
+        let Some(positions) = pattern.variables.remove(&field_name) else {
+            let message = if format_pattern.variables.contains_key(&field_name) {
+                format!("Field '{}' is fixed more than once", field_name)
+            } else {
+                format!("Format '{}' has no field named '{}'", name, field_name)
+            };
+            errors.push(syn::Error::new(field.span(), message));
+            continue;
+        };
+        let Some(value) = errors.check(value_lit.base10_parse::<u64>()) else {
+            continue;
+        };
+        if positions.len() < 64 && value >> positions.len() != 0 {
+            errors.push(syn::Error::new(
+                value_lit.span(),
+                format!(
+                    "Value {} does not fit the {} bit field '{}'",
+                    value,
+                    positions.len(),
+                    field_name
+                ),
+            ));
+            continue;
+        }
+
+        let field_mask = scatter_bits(u64::MAX, &positions);
+        pattern.mask |= field_mask;
+        pattern.value |= scatter_bits(value, &positions);
+    }
+
+    errors.finish()?;
+    Ok(Some(pattern))
+}
+
+/// Returns the pattern variables of an entry that are expanded into const values.
+///
+/// These are the handler's const generics, the variables bound in the where clause,
+/// and the variables the bound expressions refer to, e.g. `x` in
+/// `m: Mode = decode_mode(m, x)`. Every other variable is either a runtime field or
+/// matched like a wildcard, so fields the handler doesn't use don't multiply the arms.
+fn expanded_variables(entry: &InstructionEntry, pattern: &ParsedPattern) -> Vec<String> {
+    fn referenced(tokens: &TokenStream2, pattern: &ParsedPattern, names: &mut Vec<String>) {
+        for token in tokens.clone() {
+            match token {
+                proc_macro2::TokenTree::Ident(ident) => {
+                    let name = ident.to_string();
+                    if pattern.variables.contains_key(&name) && !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+                proc_macro2::TokenTree::Group(group) => referenced(&group.stream(), pattern, names),
+                _ => {}
+            }
+        }
+    }
+
+    let bindings = entry
+        .where_clause
+        .as_ref()
+        .map(|wc| wc.bindings.as_slice())
+        .unwrap_or(&[]);
+    let mut names: Vec<String> = Vec::new();
+    let used = entry
+        .handler
+        .generics
+        .iter()
+        .chain(bindings.iter().filter(|b| !b.constant).map(|b| &b.name));
+    for name in used.map(Ident::to_string) {
+        if pattern.variables.contains_key(&name) && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    for binding in bindings.iter().filter(|b| !b.constant) {
+        if let BindingValue::ConstFn(expr) = &binding.value {
+            referenced(expr, pattern, &mut names);
+        }
+    }
+    names
+}
+
+/// Generates all possible opcode values by expanding pattern variables.
+///
+/// For a pattern like `"0001'00rr"` with `r: Register = { 00 => R0, 01 => R1 }`,
+/// this generates 2 variants:
+/// - `0b00010000` with binding `r -> R0`
+/// - `0b00010001` with binding `r -> R1`
+///
+/// Each variant includes the complete opcode value and the const generic bindings
+/// to pass to the handler function.
+///
+/// # Returns
+/// Vec of `(opcode_value, bindings)` where bindings is a list of
+/// `(var_name, var_ident, const_value)` tuples.
+fn generate_opcode_variants(
+    pattern: &ParsedPattern,
+    bindings: &[VariableBinding],
+) -> Vec<OpcodeVariant> {
+    // Collect all variables with their bit positions, binding types and literal types
+    let mut var_info: Vec<VariableInfo> = Vec::new();
+
+    for (var_name, positions) in &pattern.variables {
+        // Check if this variable has a binding in the where clause
+        let binding = bindings.iter().find(|b| !b.constant && b.name == *var_name);
+
+        let value = match binding {
+            Some(binding) => binding.value.clone(),
+            None => {
+                // No binding - use raw bit value (for primitive type generics)
+                let var_ident = format_ident!("{}", var_name);
+                BindingValue::ConstFn(quote! { #var_ident })
+            }
+        };
+
+        // Declared integer types are checked to fit every value by `validate_entry`
+        let literal_type = binding
+            .and_then(declared_int_type)
+            .cloned()
+            .unwrap_or_else(|| smallest_unsigned(positions.len() as u32));
+
+        var_info.push((var_name, positions, value, literal_type));
+    }
+
+    // Collect phantom variables (`const` bindings that don't correspond to pattern
+    // variables). These are constant expressions that should be added to every variant
+    let mut phantom_bindings: Vec<Binding> = Vec::new();
+    for binding in bindings {
+        let name = binding.name.to_string();
+        if binding.constant {
+            // This is a phantom variable - evaluate it as a constant
+            let value = match &binding.value {
+                BindingValue::Mappings(mappings) => {
+                    // Phantom variables with manual mappings have exactly one variant,
+                    // checked by `validate_entry`
+                    let variant = &mappings[0].variant;
+                    if let Some(enum_type) = &binding.enum_type {
+                        quote! { #enum_type::#variant }
+                    } else {
+                        quote! { #variant }
+                    }
+                }
+                BindingValue::ConstFn(expr) => {
+                    // Const expression - use as-is (no variables to substitute)
+                    expr.clone()
+                }
+            };
+            phantom_bindings.push((name, binding.name.clone(), value));
+        }
+    }
+
+    if var_info.is_empty() {
+        // No pattern variables, just return the base pattern with phantom bindings
+        return vec![(pattern.value, phantom_bindings)];
+    }
+
+    // Recursively generate all combinations of variable values
+    let mut results = Vec::new();
+    generate_combinations(
+        pattern.value,
+        &var_info,
+        0,
+        vec![],
+        HashMap::new(),
+        &mut results,
+    );
+
+    // Add phantom bindings to all results
+    if !phantom_bindings.is_empty() {
+        for (_opcode, bindings_vec) in &mut results {
+            bindings_vec.extend(phantom_bindings.clone());
+        }
+    }
+
+    results
+}
+
+/// Scatters the bits of a variable value into its opcode bit positions.
+///
+/// `positions` are MSB first, so the highest bit of `value` lands in `positions[0]`
+/// and the lowest bit in the last position. Bits of `value` beyond the number of
+/// positions are ignored.
+fn scatter_bits(value: u64, positions: &[u8]) -> u64 {
+    positions
+        .iter()
+        .rev()
+        .enumerate()
+        .filter(|(i, _)| value & (1 << i) != 0)
+        .fold(0, |opcode, (_, &pos)| opcode | (1 << pos))
+}
+
+/// Holds raw binding information before substitution
+#[derive(Clone)]
+enum RawBinding {
+    /// Manual enum variant mapping
+    Variant(TokenStream2),
+    /// Const function expression that needs variable substitution
+    ConstFnExpr(TokenStream2),
+}
+
+/// Recursively generates all combinations of variable assignments.
+///
+/// This expands the cartesian product of all possible variable values.
+/// For manual mappings, it uses the explicit variant list.
+/// For const functions, it generates all possible bit values (0 to 2^num_bits - 1).
+fn generate_combinations(
+    current_opcode: u64,
+    var_info: &[VariableInfo],
+    index: usize,
+    current_bindings: Vec<(String, Ident, RawBinding)>,
+    current_bit_values: HashMap<String, proc_macro2::Literal>,
+    results: &mut Vec<OpcodeVariant>,
+) {
+    if index >= var_info.len() {
+        // At the leaf: all variables have been expanded
+        // Now substitute all variables in const function expressions
+        let final_bindings: Vec<Binding> = current_bindings
+            .into_iter()
+            .map(|(name, ident, raw_binding)| {
+                let value = match raw_binding {
+                    RawBinding::Variant(v) => v,
+                    RawBinding::ConstFnExpr(expr) => {
+                        // Substitute all variables in the expression
+                        substitute_all_variables(&expr, &current_bit_values)
+                    }
+                };
+                (name, ident, value)
+            })
+            .collect();
+
+        results.push((current_opcode, final_bindings));
+        return;
+    }
+
+    let (var_name, positions, binding_value, literal_type) = &var_info[index];
+
+    match binding_value {
+        BindingValue::Mappings(mappings) => {
+            // Manual mapping: expand all bit patterns
+            for mapping in mappings {
+                let bits_value =
+                    u64::from_str_radix(&mapping.bits, 2).expect("Mapping bits are binary");
+                let new_opcode = current_opcode | scatter_bits(bits_value, positions);
+
+                let mut new_bindings = current_bindings.clone();
+                let variant = &mapping.variant;
+                new_bindings.push((
+                    var_name.to_string(),
+                    format_ident!("{}", var_name),
+                    RawBinding::Variant(quote! { #variant }),
+                ));
+
+                let mut new_bit_values = current_bit_values.clone();
+                new_bit_values.insert(
+                    var_name.to_string(),
+                    typed_literal(bits_value, literal_type),
+                );
+
+                generate_combinations(
+                    new_opcode,
+                    var_info,
+                    index + 1,
+                    new_bindings,
+                    new_bit_values,
+                    results,
+                );
+            }
+        }
+        BindingValue::ConstFn(fn_expr) => {
+            // Const function: expand all possible bit values. Variables have at least
+            // one bit and at most 64, which `1 << len` would overflow
+            let max_value = u64::MAX >> (64 - positions.len());
+            for bits_value in 0..=max_value {
+                let new_opcode = current_opcode | scatter_bits(bits_value, positions);
+
+                let mut new_bindings = current_bindings.clone();
+                let var_ident = format_ident!("{}", var_name);
+
+                // Store the raw expression - we'll substitute later when we have all bit values
+                new_bindings.push((
+                    var_name.to_string(),
+                    var_ident,
+                    RawBinding::ConstFnExpr(fn_expr.clone()),
+                ));
+
+                let mut new_bit_values = current_bit_values.clone();
+                new_bit_values.insert(
+                    var_name.to_string(),
+                    typed_literal(bits_value, literal_type),
+                );
+
+                generate_combinations(
+                    new_opcode,
+                    var_info,
+                    index + 1,
+                    new_bindings,
+                    new_bit_values,
+                    results,
+                );
+            }
+        }
+    }
+}
+
+/// Substitutes multiple variables in a token stream.
+///
+/// Used for const functions that reference multiple variables like `decode_mode(m, x)`.
+/// This replaces all variable identifiers with their concrete bit values.
+///
+/// # Arguments
+/// * `tokens` - The token stream to process (e.g., the const function call)
+/// * `var_bit_values` - Map of variable names to their typed bit value literals
+fn substitute_all_variables(
+    tokens: &TokenStream2,
+    var_bit_values: &HashMap<String, proc_macro2::Literal>,
+) -> TokenStream2 {
+    let mut result = TokenStream2::new();
+
+    for tt in tokens.clone().into_iter() {
+        match tt {
+            proc_macro2::TokenTree::Ident(ref ident) => {
+                let ident_str = ident.to_string();
+                if let Some(bit_lit) = var_bit_values.get(&ident_str) {
+                    // Replace variable with bit value
+                    let mut bit_lit = bit_lit.clone();
+                    bit_lit.set_span(ident.span());
+                    result.extend(std::iter::once(proc_macro2::TokenTree::Literal(bit_lit)));
+                } else {
+                    // Keep the identifier as-is
+                    result.extend(std::iter::once(tt));
+                }
+            }
+            proc_macro2::TokenTree::Group(group) => {
+                // Recursively process grouped tokens
+                let inner = substitute_all_variables(&group.stream(), var_bit_values);
+                let new_group = proc_macro2::Group::new(group.delimiter(), inner);
+                result.extend(std::iter::once(proc_macro2::TokenTree::Group(new_group)));
+            }
+            _ => {
+                result.extend(std::iter::once(tt));
+            }
+        }
+    }
+
+    result
+}
+
+/// Returns whether `value` is in the range of the primitive integer type `ty`.
+fn int_fits(value: u64, ty: &Ident) -> bool {
+    match int_type_info(ty) {
+        Some((bits, signed)) => {
+            let value_bits = if signed { bits - 1 } else { bits };
+            value_bits >= 64 || value < (1u64 << value_bits)
+        }
+        None => false,
+    }
+}
+
+/// Creates the suffixed literal substituted for a variable's bit value.
+///
+/// The value must fit the type, see `int_fits`.
+fn typed_literal(value: u64, ty: &Ident) -> proc_macro2::Literal {
+    match ty.to_string().as_str() {
+        "u8" => proc_macro2::Literal::u8_suffixed(value as u8),
+        "u16" => proc_macro2::Literal::u16_suffixed(value as u16),
+        "u32" => proc_macro2::Literal::u32_suffixed(value as u32),
+        "u64" => proc_macro2::Literal::u64_suffixed(value),
+        "i8" => proc_macro2::Literal::i8_suffixed(value as i8),
+        "i16" => proc_macro2::Literal::i16_suffixed(value as i16),
+        "i32" => proc_macro2::Literal::i32_suffixed(value as i32),
+        _ => proc_macro2::Literal::i64_suffixed(value as i64),
+    }
+}
+
+/// Creates an opcode literal of the given bit width.
+///
+/// Bit widths are validated against the opcode type before code generation, so only
+/// 8, 16, 32 and 64 reach this point.
+fn make_literal(value: u64, bit_width: usize) -> proc_macro2::Literal {
+    match bit_width {
+        8 => proc_macro2::Literal::u8_suffixed(value as u8),
+        16 => proc_macro2::Literal::u16_suffixed(value as u16),
+        32 => proc_macro2::Literal::u32_suffixed(value as u32),
+        64 => proc_macro2::Literal::u64_suffixed(value),
+        _ => unreachable!("unvalidated bit width: {}", bit_width),
+    }
+}
+
+/// Returns the mask covering all bits of an opcode of the given bit width.
+fn make_full_mask(bit_width: usize) -> u64 {
+    match bit_width {
+        8 => 0xFF,
+        16 => 0xFFFF,
+        32 => 0xFFFF_FFFF,
+        64 => 0xFFFF_FFFF_FFFF_FFFF,
+        _ => unreachable!("unvalidated bit width: {}", bit_width),
+    }
+}
+
+/// Returns the bit width and signedness of a primitive integer type name.
+fn int_type_info(ty: &Ident) -> Option<(u32, bool)> {
+    match ty.to_string().as_str() {
+        "u8" => Some((8, false)),
+        "u16" => Some((16, false)),
+        "u32" => Some((32, false)),
+        "u64" => Some((64, false)),
+        "i8" => Some((8, true)),
+        "i16" => Some((16, true)),
+        "i32" => Some((32, true)),
+        "i64" => Some((64, true)),
+        _ => None,
+    }
+}
+
+/// Returns the smallest unsigned integer type that holds `bits` bits.
+fn smallest_unsigned(bits: u32) -> Ident {
+    match bits {
+        0..=8 => format_ident!("u8"),
+        9..=16 => format_ident!("u16"),
+        17..=32 => format_ident!("u32"),
+        _ => format_ident!("u64"),
+    }
+}
+
+/// Returns the number of bits of the largest value of a `field_bits` wide field
+/// multiplied by `scale`.
+fn scaled_bits(field_bits: u32, scale: u64) -> u32 {
+    field_bits + (64 - (scale - 1).leading_zeros())
+}
+
+/// Returns the smallest and largest value of a `field_bits` wide field multiplied by
+/// `scale`, `None` if they don't fit an `i128`.
+fn scaled_range(field_bits: u32, scale: u64, signed: bool) -> Option<(i128, i128)> {
+    let (min, max) = if signed {
+        let half = 1i128 << (field_bits - 1);
+        (-half, half - 1)
+    } else {
+        (0, (1i128 << field_bits) - 1)
+    };
+    let scale = i128::from(scale);
+    Some((min.checked_mul(scale)?, max.checked_mul(scale)?))
+}
+
+/// Returns the type a runtime field is passed as: the declared type, or the smallest
+/// unsigned type that holds the (scaled) field.
+fn runtime_arg_type(arg: &RuntimeArg, field_bits: u32) -> Ident {
+    // Invalid scales are reported by `generate_field_extraction`
+    let scale = arg
+        .scale
+        .as_ref()
+        .and_then(|scale| scale.base10_parse().ok())
+        .unwrap_or(1)
+        .max(1);
+    arg.ty
+        .clone()
+        .unwrap_or_else(|| smallest_unsigned(scaled_bits(field_bits, scale)))
+}
+
+/// Generates the expression extracting a runtime field from `opcode`.
+///
+/// The field bits are gathered MSB first, one shift and mask per contiguous run of
+/// bits. The result is converted to the argument type (sign-extending for signed
+/// types) and multiplied by the scale.
+fn generate_field_extraction(
+    arg: &RuntimeArg,
+    positions: &[u8],
+    bit_width: usize,
+) -> syn::Result<TokenStream2> {
+    let field_bits = positions.len() as u32;
+    let scale: u64 = match &arg.scale {
+        Some(scale) => {
+            let value = scale.base10_parse()?;
+            if value == 0 {
+                return Err(syn::Error::new(
+                    scale.span(),
+                    format!("Scale of runtime field '{}' must not be zero", arg.name),
+                ));
+            }
+            value
+        }
+        None => 1,
+    };
+
+    // Bits needed for the largest (scaled) value, to check it fits the argument type
+    let magnitude_bits = scaled_bits(field_bits, scale);
+    let ty = runtime_arg_type(arg, field_bits);
+    let Some((type_bits, signed)) = int_type_info(&ty) else {
+        return Err(syn::Error::new(
+            ty.span(),
+            format!(
+                "Runtime field '{}' must be an integer type (u8-u64, i8-i64), got '{}'",
+                arg.name, ty
+            ),
+        ));
+    };
+    if magnitude_bits > type_bits {
+        let size = match scaled_range(field_bits, scale, signed) {
+            _ if scale == 1 => format!("has {} bits", field_bits),
+            Some((min, max)) => {
+                format!("ranges from {} to {} after scaling by {}", min, max, scale)
+            }
+            None => format!("needs {} bits after scaling by {}", magnitude_bits, scale),
+        };
+        return Err(syn::Error::new(
+            arg.ty.as_ref().unwrap_or(&arg.name).span(),
+            format!(
+                "Runtime field '{}' {} and does not fit '{}'",
+                arg.name, size, ty
+            ),
+        ));
+    }
+
+    // Gather every run of contiguous bits into its place in the field value
+    let mut terms = Vec::new();
+    let mut index = 0;
+    while index < positions.len() {
+        let mut len = 1;
+        while index + len < positions.len()
+            && positions[index + len] + 1 == positions[index + len - 1]
+        {
+            len += 1;
+        }
+        let low = positions[index + len - 1] as u32;
+        let dest = (positions.len() - index - len) as u32;
+        let run_mask = make_literal(u64::MAX >> (64 - len), bit_width);
+
+        let mut term = if low == 0 {
+            quote! { (opcode & #run_mask) }
+        } else {
+            quote! { ((opcode >> #low) & #run_mask) }
+        };
+        if dest > 0 {
+            term = quote! { (#term << #dest) };
+        }
+        terms.push(term);
+        index += len;
+    }
+
+    let mut value = quote! { ((#(#terms)|*) as #ty) };
+    if signed && field_bits < type_bits {
+        // Move the field's sign bit to the top and shift back arithmetically
+        let shift = type_bits - field_bits;
+        value = quote! { ((#value << #shift) >> #shift) };
+    }
+    if scale > 1 {
+        let scale = proc_macro2::Literal::u64_unsuffixed(scale);
+        value = quote! { (#value * #scale) };
+    }
+    Ok(value)
+}
+
+/// Generates the statement packing a runtime field into its opcode bits, the inverse of
+/// `generate_field_extraction`.
+///
+/// The field's variable is rebound to its bits in place as a `u64`. Values that aren't a
+/// multiple of the scale or don't fit the field break out of the `'entry` block, so the
+/// encoder tries the next entry.
+fn generate_field_packing(arg: &RuntimeArg, positions: &[u8]) -> TokenStream2 {
+    let name = &arg.name;
+    let field_bits = positions.len() as u32;
+    // Invalid scales and types are reported by `generate_field_extraction`
+    let scale: u64 = arg
+        .scale
+        .as_ref()
+        .and_then(|scale| scale.base10_parse().ok())
+        .unwrap_or(1)
+        .max(1);
+    let signed = int_type_info(&runtime_arg_type(arg, field_bits)).is_some_and(|(_, s)| s);
+    let (min, max) = if signed {
+        (
+            -(1i128 << (field_bits - 1)),
+            (1i128 << (field_bits - 1)) - 1,
+        )
+    } else {
+        (0, (1i128 << field_bits) - 1)
+    };
+    let min = proc_macro2::Literal::i128_unsuffixed(min);
+    let max = proc_macro2::Literal::i128_unsuffixed(max);
+    let descale = (scale > 1).then(|| {
+        let scale = proc_macro2::Literal::u64_unsuffixed(scale);
+        quote! {
+            if value % #scale != 0 {
+                break 'entry;
+            }
+            let value = value / #scale;
+        }
+    });
+
+    // Scatter every run of contiguous bits from the field value into its place
+    let mut terms = Vec::new();
+    let mut index = 0;
+    while index < positions.len() {
+        let mut len = 1;
+        while index + len < positions.len()
+            && positions[index + len] + 1 == positions[index + len - 1]
+        {
+            len += 1;
+        }
+        let low = positions[index + len - 1] as u32;
+        let source = (positions.len() - index - len) as u32;
+        let run_mask = proc_macro2::Literal::u64_suffixed(u64::MAX >> (64 - len));
+        terms.push(quote! { (((bits >> #source) & #run_mask) << #low) });
+        index += len;
+    }
+
+    quote! {
+        let #name: u64 = {
+            let value = #name as i128;
+            #descale
+            if !(#min..=#max).contains(&value) {
+                break 'entry;
+            }
+            let bits = value as u64;
+            #(#terms)|*
+        };
+    }
+}
+
+/// Returns the bit width of a primitive unsigned opcode type like `u16`.
+///
+/// Returns `None` for other types (e.g., type aliases), the width is then taken from
+/// the patterns.
+fn opcode_bit_width(ty: &Type) -> Option<usize> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    match int_type_info(path.path.get_ident()?)? {
+        (bits, false) => Some(bits as usize),
+        _ => None,
+    }
+}
+
+/// Generates the expression evaluated by the dispatcher for opcodes that no pattern
+/// matches: a call to the `fallback` handler, the `fallback` value, or a panic by
+/// default.
+fn unhandled_opcode(table: &InstructionTable, dispatcher: &Dispatcher) -> TokenStream2 {
+    match &dispatcher.fallback {
+        Some(Fallback::Handler { method, name }) => generate_call(
+            dispatcher,
+            name,
+            *method,
+            Vec::new(),
+            &handler_args(table, dispatcher),
+        ),
+        Some(Fallback::Value(value)) => quote! { #value },
+        None => quote! { panic!("Unhandled opcode: 0x{:02X}", opcode) },
+    }
+}
+
+/// Generates the arguments every handler receives: the dispatcher's parameters, with
+/// the opcode converted if `handler_opcode` is specified.
+fn handler_args(table: &InstructionTable, dispatcher: &Dispatcher) -> Vec<TokenStream2> {
+    let opcode_arg = match &table.handler_opcode_type {
+        Some(handler_type) => quote! { #handler_type(opcode) },
+        None => quote! { opcode },
+    };
+    dispatcher.signature.args(&opcode_arg)
+}
+
+/// Generates a call of the handler `name` with the given const generic arguments.
+///
+/// Functions are called as `module::name::<generics>(args)`, relative to the module of
+/// the dispatcher and with its generics forwarded before `const_args`. Methods
+/// (`self.name` or `handlers = method`) are called on the first argument instead, as
+/// `receiver.name::<const_args>(rest)`. The generics of the dispatcher belong to the
+/// impl of a method, so they aren't forwarded.
+fn generate_call(
+    dispatcher: &Dispatcher,
+    name: &Path,
+    method: bool,
+    const_args: Vec<TokenStream2>,
+    args: &[TokenStream2],
+) -> TokenStream2 {
+    let method = method || dispatcher.handlers == Handlers::Method;
+    if method
+        && dispatcher.signature.has_receiver()
+        && let Some((receiver, args)) = args.split_first()
+    {
+        return if const_args.is_empty() {
+            quote! { #receiver.#name(#(#args),*) }
+        } else {
+            quote! { #receiver.#name::<#(#const_args),*>(#(#args),*) }
+        };
+    }
+
+    let generic_args: Vec<TokenStream2> = dispatcher
+        .signature
+        .generic_args()
+        .into_iter()
+        .chain(const_args)
+        .collect();
+    let name = match &dispatcher.module {
+        Some(module) => quote! { #module::#name },
+        None => quote! { #name },
+    };
+    if generic_args.is_empty() {
+        quote! { #name(#(#args),*) }
+    } else {
+        quote! { #name::<#(#generic_args),*>(#(#args),*) }
+    }
+}
+
+/// Generates a handler call with const generic arguments.
+///
+/// The const generic arguments are the values of the handler's generics, see
+/// `generic_values`. The runtime field extractions are passed after the
+/// `handler_args`.
+fn generate_handler_call(
+    dispatcher: &Dispatcher,
+    handler: &HandlerSpec,
+    bindings: &[Binding],
+    where_clause: &Option<WhereClause>,
+    handler_args: &[TokenStream2],
+    runtime_args: &[TokenStream2],
+) -> TokenStream2 {
+    let const_args = generic_values(handler, bindings, where_clause);
+    let args: Vec<TokenStream2> = handler_args.iter().chain(runtime_args).cloned().collect();
+    generate_call(dispatcher, &handler.name, handler.method, const_args, &args)
+}
+
+/// Generates the values of a handler's generics in one expanded variant of an entry,
+/// see `variable_value`.
+fn generic_values(
+    handler: &HandlerSpec,
+    bindings: &[Binding],
+    where_clause: &Option<WhereClause>,
+) -> Vec<TokenStream2> {
+    // Generics are checked against the pattern variables by `validate_entry`
+    handler
+        .generics
+        .iter()
+        .map(|var_name| {
+            variable_value(&var_name.to_string(), bindings, where_clause)
+                .expect("handler generic has a binding")
+        })
+        .collect()
+}
+
+/// Generates the value of a variable in one expanded variant of an entry, `None` if it
+/// has no binding.
+///
+/// Looks up variable values from bindings and wraps them appropriately:
+/// - Enum variants: Adds type prefix (e.g., `Register::R0`)
+/// - Const fn results: Wraps in braces (e.g., `{ decode_mode(0u8) }`)
+/// - Primitive values: Wraps in braces, typed by the variable's width or declared
+///   integer type (e.g., `{ 0u8 }`, `{ 300u16 }`)
+fn variable_value(
+    var_name: &str,
+    bindings: &[Binding],
+    where_clause: &Option<WhereClause>,
+) -> Option<TokenStream2> {
+    // Find the const value for this variable
+    let value = bindings
+        .iter()
+        .find(|(name, _, _)| var_name == name)
+        .map(|(_, _, value)| value)?;
+
+    // Look up the type annotation from the where clause
+    let enum_type = where_clause.as_ref().and_then(|wc| {
+        wc.bindings
+            .iter()
+            .find(|b| b.name == var_name)
+            .and_then(|b| b.enum_type.as_ref())
+    });
+
+    // Check if the value is a simple identifier (enum variant) rather than a literal
+    let value_str = value.to_string();
+    let value = if value_str.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !value_str.starts_with(|c: char| c.is_ascii_digit())
+    {
+        // Simple identifier - could be an enum variant
+        if let Some(enum_type) = enum_type {
+            // Add enum type prefix for variants
+            quote! { { #enum_type::#value } }
+        } else {
+            // Primitive value or untyped
+            quote! { { #value } }
+        }
+    } else {
+        // Complex expression (const function call) - already complete
+        quote! { { #value } }
+    };
+    Some(value)
+}
+
+/// Returns the declared type of a binding if it's a primitive integer type.
+fn declared_int_type(binding: &VariableBinding) -> Option<&Ident> {
+    binding
+        .enum_type
+        .as_ref()
+        .and_then(|ty| ty.get_ident())
+        .filter(|ty| int_type_info(ty).is_some())
+}
+
+/// Checks that method handlers (`self.name` or `handlers = method`) can be called.
+///
+/// Methods are called on the first parameter of the signature, so it must not be the
+/// opcode, and they are named by a single identifier instead of a path.
+fn validate_methods(table: &InstructionTable, dispatcher: &Dispatcher) -> syn::Result<()> {
+    let mut errors = Errors::default();
+    let fallback = match &dispatcher.fallback {
+        Some(Fallback::Handler { method, name }) => Some((*method, name)),
+        _ => None,
+    };
+    let handlers = table
+        .entries
+        .iter()
+        .map(|entry| (entry.handler.method, &entry.handler.name))
+        .chain(fallback);
+
+    for (method, name) in handlers {
+        if !method && dispatcher.handlers != Handlers::Method {
+            continue;
+        }
+        if name.get_ident().is_none() {
+            errors.push(syn::Error::new_spanned(
+                name,
+                "Method handlers are named by a single identifier, not a path",
+            ));
+        } else if !dispatcher.signature.has_receiver() {
+            errors.push(syn::Error::new_spanned(
+                name,
+                "Method handlers are called on the first parameter of the signature, \
+                 which can't be the opcode",
+            ));
+        }
+    }
+
+    errors.finish()
+}
+
+/// The types of the values an entry passes to its handler, as `(name, type)` pairs:
+/// the const generics and the runtime fields.
+type ValueTypes<'a> = (Vec<(&'a Ident, TokenStream2)>, Vec<(&'a Ident, Ident)>);
+
+/// Returns the types of the values an entry passes to its handler.
+///
+/// Const generics are typed by the where clause, or by the width of their variable
+/// without a binding. Runtime fields have the type they are extracted as. Bindings
+/// without a type are an error, `user` names what needs the types.
+fn handler_value_types<'a>(
+    entry: &'a InstructionEntry,
+    pattern: &ParsedPattern,
+    user: &str,
+) -> syn::Result<ValueTypes<'a>> {
+    let mut errors = Errors::default();
+    let bindings = entry
+        .where_clause
+        .as_ref()
+        .map(|wc| wc.bindings.as_slice())
+        .unwrap_or_default();
+
+    let mut generics = Vec::new();
+    for var in &entry.handler.generics {
+        let ty = match bindings.iter().find(|b| b.name == *var) {
+            Some(VariableBinding {
+                enum_type: Some(ty),
+                ..
+            }) => quote! { #ty },
+            Some(_) => {
+                errors.push(syn::Error::new(
+                    var.span(),
+                    format!(
+                        "{} needs the type of '{}', declare it in the where clause: \
+                         `{}: Type = ...`",
+                        user, var, var
+                    ),
+                ));
+                continue;
+            }
+            None => {
+                let bits = pattern.variables.get(&var.to_string()).map_or(0, Vec::len);
+                let ty = smallest_unsigned(bits as u32);
+                quote! { #ty }
+            }
+        };
+        generics.push((var, ty));
+    }
+
+    let args = entry.handler.args.iter().map(|arg| {
+        let bits = pattern
+            .variables
+            .get(&arg.name.to_string())
+            .map_or(0, Vec::len);
+        (&arg.name, runtime_arg_type(arg, bits as u32))
+    });
+    let args = args.collect();
+
+    errors.finish()?;
+    Ok((generics, args))
+}
+
+/// A method of the `handler_trait`: its name, const generic parameters, runtime field
+/// parameters and the patterns it handles.
+struct TraitMethod {
+    /// Name of the handler
+    name: Ident,
+    /// Const generic parameters, e.g. `const R: Register`
+    generics: Vec<TokenStream2>,
+    /// Runtime field parameters after the signature's, e.g. `imm: u8`
+    args: Vec<TokenStream2>,
+    /// Types of the generics and runtime fields, which must agree between entries
+    shape: Vec<String>,
+    /// Description of the first entry using the handler, for errors
+    entry: String,
+    /// Doc lines listing the patterns handled
+    docs: Vec<String>,
+}
+
+/// Generates the `handler_trait`, with one method per distinct handler.
+///
+/// Methods take the first parameter of the signature as `self`, `&self` or
+/// `&mut self` and the other parameters in order. Const generics are named after
+/// their variable in uppercase and typed by the where clause, or by the width of the
+/// variable without a binding. Runtime fields follow with the type they are passed
+/// as. A handler used by several entries must have the same types in all of them.
+fn generate_handler_trait(
+    table: &InstructionTable,
+    dispatcher: &Dispatcher,
+    trait_name: &Ident,
+    patterns: &[Option<ParsedPattern>],
+) -> syn::Result<TokenStream2> {
+    let signature = &dispatcher.signature;
+    let Some(SignatureParam::Forwarded { ty: receiver, .. }) = signature.params.first() else {
+        // Method handlers without a receiver are reported by `validate_methods`
+        return Ok(TokenStream2::new());
+    };
+    let receiver = match &**receiver {
+        Type::Reference(reference) => {
+            let lifetime = &reference.lifetime;
+            let mutability = &reference.mutability;
+            quote! { &#lifetime #mutability self }
+        }
+        _ => quote! { self },
+    };
+    let opcode_type = table
+        .handler_opcode_type
+        .as_ref()
+        .unwrap_or(&table.opcode_type);
+    let inputs = signature.inputs(&quote! { #opcode_type });
+
+    let mut errors = Errors::default();
+    let mut candidates: Vec<(TraitMethod, &Path)> = Vec::new();
+
+    for (entry, pattern) in table.entries.iter().zip(patterns) {
+        // Paths are reported by `validate_methods`, invalid entries by their checks
+        let (Some(pattern), Some(name)) = (pattern, entry.handler.name.get_ident()) else {
+            continue;
+        };
+        let Some((generics, args)) =
+            errors.check(handler_value_types(entry, pattern, "The handler trait"))
+        else {
+            continue;
+        };
+
+        let mut shape = Vec::new();
+        let generics = generics.iter().map(|(var, ty)| {
+            let param = format_ident!("{}", var.to_string().to_uppercase());
+            shape.push(ty.to_string());
+            quote! { const #param: #ty }
+        });
+        let generics = generics.collect();
+        let args = args.iter().map(|(name, ty)| {
+            shape.push(ty.to_string());
+            quote! { #name: #ty }
+        });
+        let args = args.collect();
+
+        candidates.push((
+            TraitMethod {
+                name: name.clone(),
+                generics,
+                args,
+                shape,
+                entry: entry.pattern.describe(),
+                docs: vec![format!(" Handles {}", entry.pattern.describe())],
+            },
+            &entry.handler.name,
+        ));
+    }
+
+    if let Some(Fallback::Handler { name: path, .. }) = &dispatcher.fallback
+        && let Some(name) = path.get_ident()
+    {
+        candidates.push((
+            TraitMethod {
+                name: name.clone(),
+                generics: Vec::new(),
+                args: Vec::new(),
+                shape: Vec::new(),
+                entry: "the fallback".to_string(),
+                docs: vec![" Handles opcodes that no entry matches".to_string()],
+            },
+            path,
+        ));
+    }
+
+    // Entries sharing a handler share its method
+    let mut methods: Vec<TraitMethod> = Vec::new();
+    for (method, span) in candidates {
+        match methods.iter_mut().find(|other| other.name == method.name) {
+            Some(other) if other.shape != method.shape => errors.push(syn::Error::new_spanned(
+                span,
+                format!(
+                    "Handler '{}' has different generic or argument types than for {}",
+                    method.name, other.entry
+                ),
+            )),
+            Some(other) => other.docs.extend(method.docs),
+            None => methods.push(method),
+        }
+    }
+    errors.finish()?;
+
+    let output = signature.output();
+    let methods = methods.iter().map(|method| {
+        let TraitMethod {
+            name,
+            generics,
+            args,
+            docs,
+            ..
+        } = method;
+        let params = inputs[1..].iter().chain(args);
+        quote! {
+            #(#[doc = #docs])*
+            fn #name<#(#generics),*>(#receiver, #(#params),*) #output;
+        }
+    });
+    let (impl_generics, _, where_clause) = signature.generics.split_for_impl();
+    let doc = format!(
+        " Handlers of the instructions dispatched by [`{}`].",
+        dispatcher.name
+    );
+    Ok(quote! {
+        #[doc = #doc]
+        pub trait #trait_name #impl_generics #where_clause {
+            #(#methods)*
+        }
+    })
+}
+
+/// Converts a snake_case name to UpperCamelCase, e.g. `add_imm` to `AddImm`.
+fn upper_camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().expect("part isn't empty");
+            first.to_uppercase().chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+/// Returns the name of the instruction variant of a handler: the last segment of its
+/// name in UpperCamelCase, e.g. `Ld` for `op::ld`, `AddImm` for `add_imm` and `Type`
+/// for `r#type`.
+fn variant_name(handler: &HandlerSpec) -> Ident {
+    let name = &handler
+        .name
+        .segments
+        .last()
+        .expect("handler has a name")
+        .ident;
+    Ident::new(&upper_camel_case(&name.unraw().to_string()), name.span())
+}
+
+/// Returns the instruction variant of every entry, named by `variant_name`. Entries
+/// reusing a handler get a number from its second use on, e.g. `Load2`, skipping
+/// names that other handlers already have.
+fn variant_names(entries: &[InstructionEntry]) -> Vec<Ident> {
+    let handlers: Vec<Ident> = entries
+        .iter()
+        .map(|entry| variant_name(&entry.handler))
+        .collect();
+    let mut names: Vec<Ident> = Vec::with_capacity(handlers.len());
+    for handler in &handlers {
+        let mut name = handler.clone();
+        let mut use_count = 1usize;
+        while names.contains(&name) || (use_count > 1 && handlers.contains(&name)) {
+            use_count += 1;
+            name = format_ident!("{}{}", handler, use_count, span = handler.span());
+        }
+        names.push(name);
+    }
+    names
+}
+
+/// Generates the decoder's instruction enum, with one variant per entry.
+///
+/// Variants are named by `variant_names` and carry the values the handler would
+/// receive as fields named after their variable: the const generics, typed like for
+/// the handler trait, followed by the runtime fields.
+fn generate_instruction_enum(
+    table: &InstructionTable,
+    decoder: &Decoder,
+    patterns: &[Option<ParsedPattern>],
+) -> syn::Result<TokenStream2> {
+    let mut errors = Errors::default();
+    let mut variants: Vec<TokenStream2> = Vec::new();
+
+    let names = variant_names(&table.entries);
+    for ((entry, pattern), name) in table.entries.iter().zip(patterns).zip(names) {
+        let Some(pattern) = pattern else {
+            continue;
+        };
+        let Some((generics, args)) =
+            errors.check(handler_value_types(entry, pattern, "The decoder"))
+        else {
+            continue;
+        };
+        let types = generics
+            .into_iter()
+            .chain(args.into_iter().map(|(name, ty)| {
+                let ty = quote! { #ty };
+                (name, ty)
+            }));
+        let fields: Vec<TokenStream2> = types.map(|(name, ty)| quote! { #name: #ty }).collect();
+
+        let doc = format!(" Decoded from {}", entry.pattern.describe());
+        variants.push(if fields.is_empty() {
+            quote! {
+                #[doc = #doc]
+                #name
+            }
+        } else {
+            quote! {
+                #[doc = #doc]
+                #name { #(#fields),* }
+            }
+        });
+    }
+    errors.finish()?;
+    let instruction = &decoder.instruction;
+    let doc = format!(" Instructions decoded by [`{}`].", decoder.name);
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum #instruction {
+            #(#variants),*
+        }
+    })
+}
+
+/// The encoder tries at most this many fillings of the wildcard bits per arm.
+const ENCODER_MAX_FILLS: usize = 16;
+
+/// Returns the values the wildcard bits of an encoder arm are filled with.
+///
+/// Zero-filled wildcards can produce an opcode that an arm matched before this one
+/// handles, which then decodes to another instruction. The arm is split into the
+/// pieces no earlier arm matches, and every piece gives the fill of its fixed wildcard
+/// bits, with the other bits zero. Runtime field bits are left to the packed fields.
+/// The fills are sorted, so zero is tried first, and limited to `ENCODER_MAX_FILLS`.
+fn encoder_fills(arms: &[Arm], index: usize, field_mask: u64, bit_width: usize) -> Vec<u64> {
+    let arm = &arms[index];
+    let cube = (arm.mask, arm.value);
+    let mut pieces = vec![cube];
+    for earlier in &arms[..index] {
+        let cut = (earlier.mask, earlier.value);
+        if !cubes_intersect(cube, cut) {
+            continue;
+        }
+        let mut remaining = Vec::with_capacity(pieces.len());
+        for &piece in &pieces {
+            subtract_cube(piece, cut, &mut remaining);
+        }
+        pieces = remaining;
+        if pieces.len() > OVERLAP_MAX_PIECES {
+            // Too fragmented to analyze, the round trip check still rejects bad fills
+            return vec![0];
+        }
+    }
+
+    let wildcards = make_full_mask(bit_width) & !arm.mask & !field_mask;
+    let mut fills: Vec<u64> = pieces
+        .iter()
+        .map(|&(mask, value)| value & mask & wildcards)
+        .collect();
+    fills.sort_unstable();
+    fills.dedup();
+    fills.truncate(ENCODER_MAX_FILLS);
+    fills
+}
+
+/// Generates the encoder method of the instruction enum and its error type.
+///
+/// Every variant tries the encoder arms of its entries in priority order, the first
+/// opcode that decodes back to the instruction is returned. Wildcard bits are filled
+/// with the values of `encoder_fills`, so that opcodes claimed by earlier arms are
+/// avoided. Instructions that no opcode decodes to, e.g. with an immediate that
+/// doesn't fit its field, are returned as an `Err(InstructionUnencodable(instruction))`.
+fn generate_encoder(
+    table: &InstructionTable,
+    decoder: &Decoder,
+    encoder: &Ident,
+    patterns: &[Option<ParsedPattern>],
+    arms: &[Arm],
+    bit_width: usize,
+) -> TokenStream2 {
+    // The variant of every entry, with its fields and encoder arms
+    let mut variants: Vec<(Ident, Vec<&Ident>, Vec<TokenStream2>)> = table
+        .entries
+        .iter()
+        .zip(variant_names(&table.entries))
+        .map(|(entry, name)| {
+            let fields = entry
+                .handler
+                .generics
+                .iter()
+                .chain(entry.handler.args.iter().map(|arg| &arg.name))
+                .collect();
+            (name, fields, Vec::new())
+        })
+        .collect();
+    let decode = &decoder.name;
+    let opcode_type = &table.opcode_type;
+    for (index, arm) in arms.iter().enumerate() {
+        let entry = &table.entries[arm.entry];
+        let candidates = &mut variants[arm.entry].2;
+
+        // Bits of the runtime fields are set by the packed fields, not the fill
+        let field_mask = patterns[arm.entry].as_ref().map_or(0, |pattern| {
+            entry
+                .handler
+                .args
+                .iter()
+                .filter_map(|arg| pattern.variables.get(&arg.name.to_string()))
+                .fold(0, |mask, positions| {
+                    mask | scatter_bits(u64::MAX, positions)
+                })
+        });
+        let call = &arm.call;
+        let round_trip = quote! {
+            // A more specific entry may decode the opcode as another instruction
+            if #decode(opcode) == ::core::option::Option::Some(*self) {
+                return ::core::result::Result::Ok(opcode);
+            }
+        };
+        let fills = encoder_fills(arms, index, field_mask, bit_width);
+        let check = match fills.as_slice() {
+            [] => continue,
+            [0] => quote! {
+                let opcode = opcode as #opcode_type;
+                #round_trip
+            },
+            fills => {
+                let fills = fills
+                    .iter()
+                    .map(|&fill| proc_macro2::Literal::u64_suffixed(fill));
+                quote! {
+                    for fill in [#(#fills),*] {
+                        let opcode = (opcode | fill) as #opcode_type;
+                        #round_trip
+                    }
+                }
+            }
+        };
+        candidates.push(quote! {
+            'entry: {
+                #call
+                #check
+            }
+        });
+    }
+
+    let instruction = &decoder.instruction;
+    let match_arms = variants.iter().map(|(name, fields, candidates)| {
+        if candidates.is_empty() {
+            // Every arm of the variant is dominated, so it has no encoding
+            quote! { #instruction::#name { .. } => {} }
+        } else if fields.is_empty() {
+            quote! { #instruction::#name => { #(#candidates)* } }
+        } else {
+            quote! { #instruction::#name { #(#fields),* } => { #(#candidates)* } }
+        }
+    });
+    let doc = format!(
+        " Encodes the instruction into the opcode that [`{}`] decodes it from.",
+        decoder.name
+    );
+    // Named after the instruction enum, e.g. `InstructionUnencodable`
+    let unencodable = format_ident!("{}Unencodable", instruction);
+    let unencodable_doc = format!(
+        " An [`{}`] that no opcode decodes to, returned by [`{}::{}`].",
+        instruction, instruction, encoder
+    );
+    quote! {
+        #[doc = #unencodable_doc]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct #unencodable(pub #instruction);
+
+        impl ::core::fmt::Display for #unencodable {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                write!(f, "No encoding for {:?}", self.0)
+            }
+        }
+
+        impl ::core::error::Error for #unencodable {}
+
+        impl #instruction {
+            #[doc = #doc]
+            pub fn #encoder(&self) -> ::core::result::Result<#opcode_type, #unencodable> {
+                match *self {
+                    #(#match_arms)*
+                }
+                ::core::result::Result::Err(#unencodable(*self))
+            }
+        }
+    }
+}
+
+/// A field of a syntax string: its name and byte range in the string.
+type SyntaxField = (String, std::ops::Range<usize>);
+
+/// Splits a syntax string into a format string with positional placeholders and the
+/// names of the fields they render, e.g. `"add {r}, #{imm:#x}"` into
+/// `"add {}, #{:#x}"` and `[r, imm]`. Braces are escaped as `{{` and `}}`.
+///
+/// Fields come with their byte range in the string, for errors.
+fn parse_syntax(syntax: &LitStr) -> syn::Result<(String, Vec<SyntaxField>)> {
+    let value = syntax.value();
+    let mut format = String::new();
+    let mut fields = Vec::new();
+    let mut chars = value.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '{' if chars.next_if(|&(_, c)| c == '{').is_some() => format.push_str("{{"),
+            '}' if chars.next_if(|&(_, c)| c == '}').is_some() => format.push_str("}}"),
+            '{' => {
+                let Some((end, _)) = chars.by_ref().find(|&(_, c)| c == '}') else {
+                    return Err(syn::Error::new(
+                        pattern_span(syntax, start..value.len()),
+                        "Unclosed `{` in syntax string, write `{{` for a brace",
+                    ));
+                };
+                let field = &value[start + 1..end];
+                let (name, spec) = match field.split_once(':') {
+                    Some((name, spec)) => (name.trim(), Some(spec)),
+                    None => (field.trim(), None),
+                };
+                if name.is_empty() {
+                    return Err(syn::Error::new(
+                        pattern_span(syntax, start..end + 1),
+                        "Syntax fields need a name: a pattern variable, a binding, `_` for \
+                         the wildcard bits or `opcode`",
+                    ));
+                }
+                format.push('{');
+                if let Some(spec) = spec {
+                    format.push(':');
+                    format.push_str(spec);
+                }
+                format.push('}');
+                fields.push((name.to_string(), start..end + 1));
+            }
+            '}' => {
+                return Err(syn::Error::new(
+                    pattern_span(syntax, start..start + 1),
+                    "Unmatched `}` in syntax string, write `}}` for a brace",
+                ));
+            }
+            c => format.push(c),
+        }
+    }
+
+    Ok((format, fields))
+}
+
+/// Checks an entry against its resolved pattern before any code is generated.
+///
+/// Reports every problem of the entry with its span:
+/// - Runtime fields that aren't pattern variables, or are also generics or bindings
+/// - Handler generics `{x}` without a pattern variable or `const` binding
+/// - Where bindings for names that aren't pattern variables (constants need an explicit
+///   `const`), `const` bindings shadowing pattern variables and duplicate bindings
+/// - Mapping bit patterns longer than their variable or mapped more than once, and
+///   constant mappings without exactly one value
+/// - Declared integer types too small for the values of their variable
+fn validate_entry(entry: &InstructionEntry, pattern: &ParsedPattern) -> syn::Result<()> {
+    let mut errors = Errors::default();
+    let bindings = entry
+        .where_clause
+        .as_ref()
+        .map(|wc| wc.bindings.as_slice())
+        .unwrap_or(&[]);
+    let is_variable = |name: &Ident| pattern.variables.contains_key(&name.to_string());
+
+    for arg in &entry.handler.args {
+        if !is_variable(&arg.name) {
+            errors.push(syn::Error::new(
+                arg.name.span(),
+                format!(
+                    "Runtime field '{}' is not a variable of the pattern",
+                    arg.name
+                ),
+            ));
+        } else if entry.handler.generics.contains(&arg.name)
+            || bindings.iter().any(|binding| binding.name == arg.name)
+        {
+            errors.push(syn::Error::new(
+                arg.name.span(),
+                format!(
+                    "Runtime field '{}' can't also be a const generic or where binding",
+                    arg.name
+                ),
+            ));
+        }
+    }
+
+    for generic in &entry.handler.generics {
+        let bound = bindings
+            .iter()
+            .any(|binding| binding.constant && binding.name == *generic);
+        if !is_variable(generic) && !bound {
+            errors.push(syn::Error::new(
+                generic.span(),
+                format!(
+                    "Variable '{}' is neither a pattern variable nor a `const` binding",
+                    generic
+                ),
+            ));
+        }
+    }
+
+    for (index, binding) in bindings.iter().enumerate() {
+        let name = &binding.name;
+        if bindings[..index].iter().any(|other| other.name == *name) {
+            errors.push(syn::Error::new(
+                name.span(),
+                format!("Variable '{}' is bound more than once", name),
+            ));
+            continue;
+        }
+
+        let width = pattern.variables.get(&name.to_string()).map(Vec::len);
+        let Some(width) = width.filter(|_| !binding.constant) else {
+            if binding.constant && width.is_some() {
+                errors.push(syn::Error::new(
+                    name.span(),
+                    format!(
+                        "Constant '{}' shadows a pattern variable, remove the `const`",
+                        name
+                    ),
+                ));
+            } else if !binding.constant {
+                errors.push(syn::Error::new(
+                    name.span(),
+                    format!(
+                        "Variable '{}' is not in the pattern. Use `const {}: Type = ...` for a constant",
+                        name, name
+                    ),
+                ));
+            } else if let BindingValue::Mappings(mappings) = &binding.value
+                && mappings.len() != 1
+            {
+                errors.push(syn::Error::new(
+                    name.span(),
+                    format!("Constant '{}' must have exactly one mapping value", name),
+                ));
+            }
+            continue;
+        };
+
+        // Every bit value of the variable must fit a declared integer type
+        let mut max_value = u64::MAX >> (64 - width);
+        if let BindingValue::Mappings(mappings) = &binding.value {
+            let mut seen: Vec<u64> = Vec::new();
+            for mapping in mappings {
+                if mapping.bits.len() > width {
+                    errors.push(syn::Error::new(
+                        mapping.lit.span(),
+                        format!(
+                            "Bit pattern `{}` is longer than the {} bit variable '{}'",
+                            mapping.lit, width, name
+                        ),
+                    ));
+                    continue;
+                }
+                let bits = u64::from_str_radix(&mapping.bits, 2).expect("Mapping bits are binary");
+                if seen.contains(&bits) {
+                    errors.push(syn::Error::new(
+                        mapping.lit.span(),
+                        format!(
+                            "Bit pattern `{}` of variable '{}' is mapped more than once",
+                            mapping.lit, name
+                        ),
+                    ));
+                }
+                seen.push(bits);
+            }
+            max_value = seen.into_iter().max().unwrap_or(0);
+        }
+        if let Some(ty) = declared_int_type(binding)
+            && !int_fits(max_value, ty)
+        {
+            errors.push(syn::Error::new(
+                ty.span(),
+                format!(
+                    "Value {} of the {} bit variable '{}' does not fit '{}'",
+                    max_value, width, name, ty
+                ),
+            ));
+        }
+    }
+
+    errors.finish()
+}
+
+/// A single expanded dispatch arm.
+///
+/// Arms are collected in priority order: an opcode is handled by the first arm
+/// whose masked bits match, exactly like the arms of the generated `match`.
+struct Arm {
+    /// Index of the table entry the arm was expanded from
+    entry: usize,
+    /// Bits that must match (fixed bits plus expanded variable bits)
+    mask: u64,
+    /// Expected values for the masked bits
+    value: u64,
+    /// The handler call for opcodes matching this arm
+    call: TokenStream2,
+}
+
+/// Expands all table entries into dispatch arms in priority order.
+///
+/// The variables of `expanded_variables` are expanded into one arm per combination.
+/// Wildcard bits, runtime fields and the other variables are left out of the arm
+/// mask. Arms shadowed by earlier arms are kept, see `drop_dominated`.
+/// Every dispatcher gets its own arms, which only differ in their handler calls,
+/// followed by the arms of the decoder, which evaluate to `Some(instruction)`, and of
+/// the disassembler, which write the entry's syntax string.
+///
+/// `patterns` holds the resolved pattern of every entry, `None` for entries that are
+/// invalid. Those entries are skipped, their errors have already been reported.
+/// Errors of all other entries are collected and returned together.
+fn collect_arms(
+    table: &InstructionTable,
+    patterns: &[Option<ParsedPattern>],
+) -> syn::Result<Vec<Vec<Arm>>> {
+    let encoder = table
+        .decoder
+        .as_ref()
+        .filter(|decoder| decoder.encoder.is_some());
+    let targets = table.dispatchers.len()
+        + usize::from(table.decoder.is_some())
+        + usize::from(encoder.is_some())
+        + usize::from(table.disassembler.is_some());
+    let mut arms: Vec<Vec<Arm>> = (0..targets).map(|_| Vec::new()).collect();
+    let mut errors = Errors::default();
+    let handler_args: Vec<Vec<TokenStream2>> = table
+        .dispatchers
+        .iter()
+        .map(|dispatcher| handler_args(table, dispatcher))
+        .collect();
+    let variant_names = variant_names(&table.entries);
+
+    for (index, (entry, pattern)) in table.entries.iter().zip(patterns).enumerate() {
+        let Some(pattern) = pattern else {
+            continue;
+        };
+        let bindings = entry
+            .where_clause
+            .as_ref()
+            .map(|wc| wc.bindings.as_slice())
+            .unwrap_or(&[]);
+
+        // Runtime fields are extracted from the opcode by the dispatcher instead of
+        // being expanded, so they are wildcards as far as matching is concerned
+        let original = pattern;
+        let mut pattern = pattern.clone();
+        let mut runtime_args = Vec::new();
+        let mut packings = Vec::new();
+        let mut entry_errors = Errors::default();
+        for arg in &entry.handler.args {
+            // Runtime fields are checked to be pattern variables by `validate_entry`
+            let positions = pattern
+                .variables
+                .remove(&arg.name.to_string())
+                .expect("runtime field is a pattern variable");
+            if encoder.is_some() {
+                packings.push(generate_field_packing(arg, &positions));
+            }
+            if let Some(arg) = entry_errors.check(generate_field_extraction(
+                arg,
+                &positions,
+                pattern.bit_width,
+            )) {
+                runtime_args.push(arg);
+            }
+        }
+
+        // Variables that aren't expanded into const values are matched like wildcards
+        let expanded = expanded_variables(entry, &pattern);
+        let unexpanded: HashMap<String, Vec<u8>> = pattern
+            .variables
+            .extract_if(|name, _| !expanded.contains(name))
+            .collect();
+
+        // The disassembler renders pattern variables, bindings, the runtime fields,
+        // the wildcard bits and the opcode
+        let mut syntax = None;
+        if table.disassembler.is_some() {
+            match &entry.syntax {
+                Some(lit) => {
+                    if let Some((format, fields)) = entry_errors.check(parse_syntax(lit)) {
+                        for (field, range) in &fields {
+                            let known = field == "_"
+                                || field == "opcode"
+                                || original.variables.contains_key(field)
+                                || bindings.iter().any(|b| b.name == field);
+                            if !known {
+                                entry_errors.push(syn::Error::new(
+                                    pattern_span(lit, range.clone()),
+                                    format!(
+                                        "Unknown syntax field '{}', expected a pattern \
+                                         variable, a binding, `_` for the wildcard bits or \
+                                         `opcode`",
+                                        field
+                                    ),
+                                ));
+                            }
+                        }
+                        let fields: Vec<String> =
+                            fields.into_iter().map(|(field, _)| field).collect();
+                        syntax = Some((format, fields));
+                    }
+                }
+                None => entry_errors.push(syn::Error::new_spanned(
+                    &entry.handler.name,
+                    "Entry has no syntax for the disassembler, add it after the handler: \
+                     `=> handler as \"...\"`",
+                )),
+            }
+        }
+        let wildcard = match &syntax {
+            Some((_, fields)) if fields.iter().any(|field| field == "_") => {
+                let positions: Vec<u8> = (0..original.bit_width as u8)
+                    .rev()
+                    .filter(|&pos| original.mask & (1 << pos) == 0)
+                    .filter(|pos| !original.variables.values().any(|v| v.contains(pos)))
+                    .collect();
+                if positions.is_empty() {
+                    entry_errors.push(syn::Error::new(
+                        entry
+                            .syntax
+                            .as_ref()
+                            .map_or(entry.pattern.span(), LitStr::span),
+                        "Syntax field `_` renders the wildcard bits, but the pattern has none",
+                    ));
+                }
+                let arg = RuntimeArg {
+                    name: format_ident!("wildcard"),
+                    ty: None,
+                    scale: None,
+                };
+                entry_errors.check(generate_field_extraction(
+                    &arg,
+                    &positions,
+                    original.bit_width,
+                ))
+            }
+            _ => None,
+        };
+        // Unexpanded variables are rendered from their raw bits, extracted at runtime
+        let raw_fields: Vec<(&String, TokenStream2)> = match &syntax {
+            Some((_, fields)) => unexpanded
+                .iter()
+                .filter(|(name, _)| fields.contains(name))
+                .filter_map(|(name, positions)| {
+                    let arg = RuntimeArg {
+                        name: format_ident!("{}", name),
+                        ty: None,
+                        scale: None,
+                    };
+                    let value = generate_field_extraction(&arg, positions, original.bit_width);
+                    entry_errors.check(value).map(|value| (name, value))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        if let Err(error) = entry_errors.finish() {
+            errors.push(error);
+            continue;
+        }
+        let pattern = &pattern;
+
+        // Expand pattern into all variable combinations
+        // E.g., "00rr" with r=2bits generates 4 opcodes: 0x00, 0x01, 0x02, 0x03
+        // Variables without bindings are expanded as raw values
+        let variants = generate_opcode_variants(pattern, bindings);
+
+        // Mark variable bits as fixed in the mask since we expanded them
+        let expanded_mask = pattern
+            .variables
+            .values()
+            .fold(pattern.mask, |mask, positions| {
+                mask | scatter_bits(u64::MAX, positions)
+            });
+
+        for (opcode, var_bindings) in variants {
+            let mut calls: Vec<TokenStream2> = table
+                .dispatchers
+                .iter()
+                .zip(&handler_args)
+                .map(|(dispatcher, handler_args)| {
+                    generate_handler_call(
+                        dispatcher,
+                        &entry.handler,
+                        &var_bindings,
+                        &entry.where_clause,
+                        handler_args,
+                        &runtime_args,
+                    )
+                })
+                .collect();
+
+            // The decoder builds the instruction from the same values
+            if let Some(decoder) = &table.decoder {
+                let instruction = &decoder.instruction;
+                let variant = &variant_names[index];
+                let names = entry
+                    .handler
+                    .generics
+                    .iter()
+                    .chain(entry.handler.args.iter().map(|arg| &arg.name));
+                let values = generic_values(&entry.handler, &var_bindings, &entry.where_clause)
+                    .into_iter()
+                    .chain(runtime_args.iter().cloned());
+                let fields: Vec<TokenStream2> = names
+                    .zip(values)
+                    .map(|(name, value)| quote! { #name: #value })
+                    .collect();
+                calls.push(if fields.is_empty() {
+                    quote! { ::core::option::Option::Some(#instruction::#variant) }
+                } else {
+                    quote! { ::core::option::Option::Some(#instruction::#variant { #(#fields),* }) }
+                });
+            }
+
+            // The encoder packs the instruction into this variant's opcode when its
+            // generic fields have the values of the variant. The wildcard bits are filled
+            // in by `generate_encoder`, which knows the arms matched before this one
+            if encoder.is_some() {
+                let value = proc_macro2::Literal::u64_suffixed(opcode);
+                let names = &entry.handler.generics;
+                let values = generic_values(&entry.handler, &var_bindings, &entry.where_clause);
+                let condition = (!names.is_empty()).then(|| {
+                    quote! {
+                        if !(#(#names == #values)&&*) {
+                            break 'entry;
+                        }
+                    }
+                });
+                let fields = entry.handler.args.iter().map(|arg| &arg.name);
+                calls.push(quote! {
+                    #condition
+                    #(#packings)*
+                    let opcode: u64 = #value #(| #fields)*;
+                });
+            }
+
+            // The disassembler writes the syntax with the values of this variant
+            if let Some((format, fields)) = &syntax {
+                let values = fields.iter().map(|field| {
+                    let runtime = entry.handler.args.iter().position(|arg| arg.name == field);
+                    let raw = raw_fields.iter().find(|(name, _)| *name == field);
+                    if let Some(index) = runtime {
+                        runtime_args[index].clone()
+                    } else if let Some((_, value)) = raw {
+                        value.clone()
+                    } else if let Some(value) =
+                        variable_value(field, &var_bindings, &entry.where_clause)
+                    {
+                        value
+                    } else if field == "_" {
+                        wildcard.clone().expect("wildcard bits are extracted")
+                    } else {
+                        quote! { opcode }
+                    }
+                });
+                calls.push(quote! { ::core::write!(out, #format #(, #values)*) });
+            }
+
+            for (call, arms) in calls.into_iter().zip(&mut arms) {
+                arms.push(Arm {
+                    entry: index,
+                    mask: expanded_mask,
+                    value: opcode,
+                    call,
+                });
+            }
+        }
+    }
+
+    errors.finish()?;
+    Ok(arms)
+}
+
+/// Drops arms that a single earlier arm completely covers.
+///
+/// Such arms can never be reached, leaving them out keeps the generated code small.
+fn drop_dominated(arms: Vec<Arm>) -> Vec<Arm> {
+    let mut kept: Vec<Arm> = Vec::with_capacity(arms.len());
+    for arm in arms {
+        let dominated = kept.iter().any(|earlier| {
+            earlier.mask & arm.mask == earlier.mask && arm.value & earlier.mask == earlier.value
+        });
+        if !dominated {
+            kept.push(arm);
+        }
+    }
+    kept
+}
+
+/// A set of opcodes, the ones matching `opcode & mask == value`.
+type Cube = (u64, u64);
+
+/// Entries are only analyzed for overlaps while subtracting earlier entries leaves at
+/// most this many disjoint pieces.
+const OVERLAP_MAX_PIECES: usize = 4096;
+
+/// Number of opcodes in a cube.
+fn cube_size(cube: Cube, bit_width: usize) -> u128 {
+    1u128 << (bit_width - cube.0.count_ones() as usize)
+}
+
+/// Returns whether two cubes share at least one opcode.
+fn cubes_intersect(a: Cube, b: Cube) -> bool {
+    (a.1 ^ b.1) & a.0 & b.0 == 0
+}
+
+/// Merges cubes that differ in a single fixed bit into one cube without that bit.
+///
+/// Expanded variables without gaps collapse back into the cube of their pattern,
+/// which keeps the overlap analysis small.
+fn merge_cubes(mut cubes: Vec<Cube>, bit_width: usize) -> Vec<Cube> {
+    for bit in 0..bit_width {
+        let bit = 1u64 << bit;
+        let present: std::collections::HashSet<Cube> = cubes.iter().copied().collect();
+        cubes.retain(|&(mask, value)| {
+            // Of a mergeable pair, the half with the bit set is dropped
+            mask & bit == 0 || value & bit == 0 || !present.contains(&(mask, value ^ bit))
+        });
+        for cube in &mut cubes {
+            if cube.0 & bit != 0 && cube.1 & bit == 0 && present.contains(&(cube.0, cube.1 | bit)) {
+                cube.0 &= !bit;
+            }
+        }
+    }
+    cubes
+}
+
+/// Removes the opcodes of `cut` from `piece`, pushing the disjoint remainder to `out`.
+fn subtract_cube(piece: Cube, cut: Cube, out: &mut Vec<Cube>) {
+    if !cubes_intersect(piece, cut) {
+        out.push(piece);
+        return;
+    }
+
+    // Split the piece on every bit the cut fixes but the piece doesn't: the half that
+    // differs from the cut survives, the other half is split further
+    let (mut mask, mut value) = piece;
+    let mut free = cut.0 & !mask;
+    while free != 0 {
+        let bit = free & free.wrapping_neg();
+        free &= !bit;
+        out.push((mask | bit, (value | (cut.1 & bit)) ^ bit));
+        mask |= bit;
+        value |= cut.1 & bit;
+    }
+}
+
+/// Lists entries in a message, eliding all but the first few.
+fn describe_entries(table: &InstructionTable, entries: &[usize]) -> String {
+    const LISTED: usize = 3;
+    let mut listed: Vec<String> = entries
+        .iter()
+        .take(LISTED)
+        .map(|&index| table.entries[index].pattern.describe())
+        .collect();
+    if entries.len() > LISTED {
+        listed.push(format!("and {} more", entries.len() - LISTED));
+    }
+    listed.join(", ")
+}
+
+/// Generates a compile-time warning pointing at `span`.
+///
+/// Stable proc macros can't emit warnings directly, so this uses a deprecated constant
+/// whose note is the message.
+fn generate_warning(span: Span, message: &str) -> TokenStream2 {
+    let name = Ident::new("OVERLAP", span);
+    quote! {
+        const _: () = {
+            #[deprecated(note = #message)]
+            const #name: () = ();
+            #name
+        };
+    }
+}
+
+/// Checks every entry for opcodes that entries matched before it already handle.
+///
+/// Entries are visited in the order of `arms`, which is the declaration order unless
+/// `priority = specificity` sorted them. An entry whose opcodes are all handled by
+/// entries matched before it is never reached, which is an error. Partially shadowed
+/// entries produce a warning listing the entries involved, unless the entry is marked
+/// `#[allow(overlap)]`. With `priority = specificity` overlaps are expected and not
+/// warned about, instead two overlapping entries with the same number of fixed bits
+/// are an error since neither is more specific. Only the `0`/`1` bits of `patterns`
+/// count, not the expanded variables. Returns the generated warnings.
+fn analyze_overlaps(
+    table: &InstructionTable,
+    patterns: &[Option<ParsedPattern>],
+    arms: &[Arm],
+    bit_width: usize,
+) -> syn::Result<TokenStream2> {
+    let mut errors = Errors::default();
+    let mut warnings = TokenStream2::new();
+
+    // The opcodes of every entry as merged cubes, with the entries in match order and
+    // the number of fixed bits of each entry
+    let mut entry_cubes: Vec<Vec<Cube>> = vec![Vec::new(); table.entries.len()];
+    let mut specificity = vec![0; table.entries.len()];
+    let mut order: Vec<usize> = Vec::new();
+    for arm in arms {
+        // The arms of an entry are always next to each other
+        if order.last() != Some(&arm.entry) {
+            order.push(arm.entry);
+        }
+        entry_cubes[arm.entry].push((arm.mask, arm.value));
+        specificity[arm.entry] = patterns[arm.entry]
+            .as_ref()
+            .map_or(0, |pattern| pattern.mask.count_ones());
+    }
+    let entry_cubes: Vec<Vec<Cube>> = entry_cubes
+        .into_iter()
+        .map(|cubes| merge_cubes(cubes, bit_width))
+        .collect();
+
+    let (matched_before, hint) = match table.priority {
+        Priority::Declaration => (
+            "earlier entries",
+            ". Move it before them if it is more specific",
+        ),
+        Priority::Specificity => ("more specific entries", ""),
+    };
+
+    for (position, &index) in order.iter().enumerate() {
+        let cubes = &entry_cubes[index];
+        let overlapping: Vec<usize> = order[..position]
+            .iter()
+            .copied()
+            .filter(|&earlier| {
+                entry_cubes[earlier]
+                    .iter()
+                    .any(|&cut| cubes.iter().any(|&cube| cubes_intersect(cube, cut)))
+            })
+            .collect();
+        if overlapping.is_empty() {
+            continue;
+        }
+
+        let entry = &table.entries[index];
+        if table.priority == Priority::Specificity
+            && let Some(&other) = overlapping
+                .iter()
+                .find(|&&earlier| specificity[earlier] == specificity[index])
+        {
+            let shared = entry_cubes[other]
+                .iter()
+                .flat_map(|&cut| cubes.iter().map(move |&cube| (cube, cut)))
+                .find(|&(cube, cut)| cubes_intersect(cube, cut))
+                .map(|(cube, cut)| (cube.0 | cut.0, cube.1 | cut.1))
+                .expect("overlapping entries share a cube");
+            errors.push(syn::Error::new(
+                entry.pattern.span(),
+                format!(
+                    "Entry is ambiguous with {}: both have {} fixed bits and match {}",
+                    table.entries[other].pattern.describe(),
+                    specificity[index],
+                    cube_pattern(shared, bit_width)
+                ),
+            ));
+            continue;
+        }
 
-    // Remove single quotes (visual separators)
-    let pattern_clean: String = pattern.chars().filter(|&c| c != '\'').collect();
-    let bit_width = pattern_clean.len();
+        // Subtract every overlapping entry, giving up if the remainder gets too fragmented
+        let mut pieces = cubes.clone();
+        for &earlier in &overlapping {
+            for &cut in &entry_cubes[earlier] {
+                let mut remaining = Vec::with_capacity(pieces.len());
+                for &piece in &pieces {
+                    subtract_cube(piece, cut, &mut remaining);
+                }
+                pieces = remaining;
+            }
+            if pieces.is_empty() || pieces.len() > OVERLAP_MAX_PIECES {
+                break;
+            }
+        }
 
-    assert!(
-        bit_width == 8 || bit_width == 16 || bit_width == 32 || bit_width == 64,
-        "Pattern must be exactly 8, 16, 32, or 64 bits. Got {} bits: {} (cleaned: {})",
-        bit_width,
-        pattern,
-        pattern_clean
-    );
+        let earlier = describe_entries(table, &overlapping);
+        if pieces.is_empty() {
+            errors.push(syn::Error::new(
+                entry.pattern.span(),
+                format!(
+                    "Entry is never reached, all of its opcodes are handled by {}: {}{}",
+                    matched_before, earlier, hint
+                ),
+            ));
+        } else if !entry.allow_overlap && table.priority == Priority::Declaration {
+            let total: u128 = cubes.iter().map(|&cube| cube_size(cube, bit_width)).sum();
+            let shadowed = if pieces.len() > OVERLAP_MAX_PIECES {
+                "Some".to_string()
+            } else {
+                let remaining: u128 = pieces
+                    .iter()
+                    .map(|&piece| cube_size(piece, bit_width))
+                    .sum();
+                format!("{} of {}", total - remaining, total)
+            };
+            let message = format!(
+                "{} opcodes of this entry are handled by earlier entries: {}. \
+                 Add `#[allow(overlap)]` to the entry if this is intended",
+                shadowed, earlier
+            );
+            warnings.extend(generate_warning(entry.pattern.span(), &message));
+        }
+    }
 
-    let mut mask = 0u64;
-    let mut value = 0u64;
-    let mut wildcard_bits = 0u64;
-    let mut var_positions: HashMap<char, Vec<u8>> = HashMap::new();
+    errors.finish()?;
+    Ok(warnings)
+}
 
-    for (i, ch) in pattern_clean.chars().enumerate() {
-        let bit_pos = (bit_width - 1 - i) as u8; // MSB first
+/// Wildcard arms are rewritten as patterns if they need at most this many ranges.
+const COMPACT_MAX_RANGES: usize = 16;
 
-        match ch {
-            '0' => {
-                mask |= 1 << bit_pos;
-                // value bit stays 0
-            }
-            '1' => {
-                mask |= 1 << bit_pos;
-                value |= 1 << bit_pos;
+/// A `match` arm produced by the compaction pass.
+enum CompactArm<'a> {
+    /// Opcodes matched by literal and range patterns, joined with `|`
+    Patterns {
+        /// The handler call shared by all merged arms
+        call: &'a TokenStream2,
+        /// Sorted, disjoint and inclusive opcode ranges
+        ranges: Vec<(u64, u64)>,
+        /// The (mask, value) pairs of all merged arms, for overlap checks
+        cubes: Vec<(u64, u64)>,
+    },
+    /// A masked guard arm (`op if op & mask == value`)
+    Guard(&'a Arm),
+}
+
+impl CompactArm<'_> {
+    /// Whether any opcode matched by this arm is also matched by `mask`/`value`
+    fn overlaps(&self, mask: u64, value: u64) -> bool {
+        let intersects = |(m, v): (u64, u64)| (m & mask) & (v ^ value) == 0;
+        match self {
+            CompactArm::Patterns { cubes, .. } => cubes.iter().any(|&cube| intersects(cube)),
+            CompactArm::Guard(arm) => intersects((arm.mask, arm.value)),
+        }
+    }
+}
+
+/// Expresses the opcodes of an arm as inclusive ranges.
+///
+/// The contiguous run of wildcard bits starting at bit 0 forms the length of each
+/// range, every combination of the remaining wildcard bits adds another range. Returns `None` if
+/// that would take more than `COMPACT_MAX_RANGES` ranges.
+fn arm_ranges(arm: &Arm, full_mask: u64) -> Option<Vec<(u64, u64)>> {
+    let free = !arm.mask & full_mask;
+    // Wildcard bits counting up from bit 0, e.g. 0b0111 for 0b1011_0111
+    let low_run = free & !free.wrapping_add(1);
+    let high_free = free & !low_run;
+    if 1usize.checked_shl(high_free.count_ones())? > COMPACT_MAX_RANGES {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+    let mut sub = high_free;
+    loop {
+        let lo = arm.value | sub;
+        ranges.push((lo, lo | low_run));
+        if sub == 0 {
+            break;
+        }
+        sub = (sub - 1) & high_free;
+    }
+    ranges.sort_unstable();
+    Some(ranges)
+}
+
+/// Removes every opcode in `remove` from `ranges`. Both must be sorted and disjoint.
+fn subtract_ranges(ranges: &[(u64, u64)], remove: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    let mut result = Vec::new();
+    for &(mut lo, hi) in ranges {
+        let mut exhausted = false;
+        for &(r_lo, r_hi) in remove {
+            if r_hi < lo || r_lo > hi {
+                continue;
             }
-            '_' | '.' => {
-                // Wildcard - don't care bit (not in mask, not a variable)
-                wildcard_bits |= 1 << bit_pos;
+            if r_lo > lo {
+                result.push((lo, r_lo - 1));
             }
-            c if c.is_ascii_lowercase() => {
-                var_positions.entry(c).or_default().push(bit_pos);
+            if r_hi >= hi {
+                exhausted = true;
+                break;
             }
-            _ => panic!(
-                "Invalid pattern character: '{}'. Use 0/1 for fixed bits, a-z for variables, _ or . for wildcards, ' for visual separation",
-                ch
-            ),
+            lo = r_hi + 1;
+        }
+        if !exhausted {
+            result.push((lo, hi));
         }
     }
+    result
+}
 
-    // Convert var positions to (start_bit, num_bits)
-    let mut variables = HashMap::new();
-    for (var_char, positions) in var_positions {
-        let min_pos = *positions.iter().min().unwrap();
-        let num_bits = positions.len() as u8;
-        variables.insert(var_char.to_string(), (min_pos, num_bits));
+/// Merges two sets of sorted, disjoint ranges, coalescing adjacent ranges.
+fn merge_ranges(a: &[(u64, u64)], b: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    let mut all: Vec<(u64, u64)> = a.iter().chain(b).copied().collect();
+    all.sort_unstable();
+
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(all.len());
+    for (lo, hi) in all {
+        match merged.last_mut() {
+            Some(last) if last.1 != u64::MAX && last.1 + 1 >= lo => last.1 = last.1.max(hi),
+            _ => merged.push((lo, hi)),
+        }
     }
+    merged
+}
 
-    ParsedPattern {
-        mask,
-        value,
-        variables,
-        wildcard_bits,
-        bit_width,
+/// Compacts the expanded arms before emitting them as a `match`.
+///
+/// Wildcard arms covering few enough ranges become literal and range patterns.
+/// An arm is merged into an earlier arm with the identical handler call, as long as
+/// no arm in between matches any of its opcodes, so the first match still wins.
+/// Opcodes already matched by earlier patterns are dropped to avoid unreachable
+/// patterns.
+fn compact_arms(arms: &[Arm], full_mask: u64) -> Vec<CompactArm<'_>> {
+    let mut compacted: Vec<CompactArm> = Vec::new();
+
+    for arm in arms {
+        let Some(ranges) = arm_ranges(arm, full_mask) else {
+            compacted.push(CompactArm::Guard(arm));
+            continue;
+        };
+
+        // Drop opcodes that earlier patterns already match
+        let mut ranges = ranges;
+        for earlier in &compacted {
+            if let CompactArm::Patterns { ranges: seen, .. } = earlier {
+                ranges = subtract_ranges(&ranges, seen);
+            }
+        }
+        if ranges.is_empty() {
+            continue;
+        }
+
+        // Find the earliest arm with the same call that nothing after it overlaps
+        let key = arm.call.to_string();
+        let mut target = None;
+        for (index, earlier) in compacted.iter().enumerate().rev() {
+            if let CompactArm::Patterns { call, .. } = earlier
+                && call.to_string() == key
+            {
+                target = Some(index);
+            }
+            if earlier.overlaps(arm.mask, arm.value) {
+                break;
+            }
+        }
+
+        match target.map(|index| &mut compacted[index]) {
+            Some(CompactArm::Patterns {
+                ranges: merged,
+                cubes,
+                ..
+            }) => {
+                *merged = merge_ranges(merged, &ranges);
+                cubes.push((arm.mask, arm.value));
+            }
+            _ => compacted.push(CompactArm::Patterns {
+                call: &arm.call,
+                ranges,
+                cubes: vec![(arm.mask, arm.value)],
+            }),
+        }
     }
+
+    compacted
 }
 
-/// Generates all possible opcode values by expanding pattern variables.
+/// Generates the dispatcher body as a single `match` expression.
 ///
-/// For a pattern like `"0001'00rr"` with `r: Register = { 00 => R0, 01 => R1 }`,
-/// this generates 2 variants:
-/// - `0b00010000` with binding `r -> R0`
-/// - `0b00010001` with binding `r -> R1`
+/// The arms are compacted first: opcodes sharing a handler call are merged into
+/// `a | b | lo..=hi` patterns where that keeps the priority order. Arms with too
+/// many scattered wildcard bits use a masked guard (`op if op & mask == value`).
+fn generate_match(arms: &[Arm], bit_width: usize, unhandled: &TokenStream2) -> TokenStream2 {
+    let full_mask = make_full_mask(bit_width);
+
+    let match_arms = compact_arms(arms, full_mask)
+        .into_iter()
+        .map(|arm| match arm {
+            CompactArm::Patterns { call, ranges, .. } => {
+                let patterns = ranges.iter().map(|&(lo, hi)| {
+                    let lo_lit = make_literal(lo, bit_width);
+                    if lo == hi {
+                        quote! { #lo_lit }
+                    } else {
+                        let hi_lit = make_literal(hi, bit_width);
+                        quote! { #lo_lit..=#hi_lit }
+                    }
+                });
+                quote! { #(#patterns)|* => { #call } }
+            }
+            CompactArm::Guard(arm) => {
+                let call = &arm.call;
+                let mask_lit = make_literal(arm.mask, bit_width);
+                let value_lit = make_literal(arm.value, bit_width);
+                quote! { op if op & #mask_lit == #value_lit => { #call } }
+            }
+        });
+
+    quote! {
+        match opcode {
+            #(#match_arms)*
+            #[allow(unreachable_patterns)]
+            _ => #unhandled,
+        }
+    }
+}
+
+/// Maximum number of opcode bits a single decision tree node switches on.
+const TREE_MAX_SWITCH_BITS: usize = 8;
+
+/// Decision tree nodes with at most this many arms are emitted as a guard chain.
+const TREE_LEAF_ARMS: usize = 3;
+
+/// Generates the dispatcher body as a decision tree of nested `match` expressions.
 ///
-/// Each variant includes the complete opcode value and the const generic bindings
-/// to pass to the handler function.
+/// Each node switches on the opcode bits that are fixed by the most remaining
+/// arms and recurses into one branch per distinct value of those bits. Arms that
+/// leave some of the switched bits open are copied into every compatible branch
+/// in their original order, so the first matching arm still wins.
+fn generate_tree(arms: &[Arm], bit_width: usize, unhandled: &TokenStream2) -> TokenStream2 {
+    let arms: Vec<&Arm> = arms.iter().collect();
+    generate_tree_node(&arms, 0, bit_width, unhandled)
+}
+
+/// Generates one decision tree node for `arms`, given the opcode bits in `known`
+/// that have already been switched on by parent nodes.
+fn generate_tree_node(
+    arms: &[&Arm],
+    known: u64,
+    bit_width: usize,
+    unhandled: &TokenStream2,
+) -> TokenStream2 {
+    let Some(first) = arms.first() else {
+        return unhandled.clone();
+    };
+
+    // All bits of the first arm were checked by parent nodes, so it always matches
+    if first.mask & !known == 0 {
+        return first.call.clone();
+    }
+
+    if arms.len() <= TREE_LEAF_ARMS {
+        return generate_tree_leaf(arms, known, bit_width, unhandled);
+    }
+
+    // Count how many arms fix each of the first arm's unchecked bits. The first
+    // arm is always split on, which guarantees progress on every level.
+    let candidates = first.mask & !known;
+    let mut scores: Vec<(usize, u32)> = (0..bit_width as u32)
+        .filter(|bit| candidates & (1 << bit) != 0)
+        .map(|bit| {
+            let count = arms.iter().filter(|arm| arm.mask & (1 << bit) != 0).count();
+            (count, bit)
+        })
+        .collect();
+    scores.sort_by(|a, b| b.cmp(a));
+
+    let best = scores[0].0;
+    let switch_mask = scores
+        .iter()
+        .take_while(|(count, _)| *count == best)
+        .take(TREE_MAX_SWITCH_BITS)
+        .fold(0u64, |mask, (_, bit)| mask | (1 << bit));
+
+    // Every value of the switched bits that some arm explicitly asks for
+    let mut keys: Vec<u64> = Vec::new();
+    for arm in arms {
+        let fixed = arm.mask & switch_mask;
+        if fixed == 0 {
+            continue;
+        }
+        let open = switch_mask & !fixed;
+        let mut sub = open;
+        loop {
+            let key = (arm.value & fixed) | sub;
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+            if sub == 0 {
+                break;
+            }
+            sub = (sub - 1) & open;
+        }
+    }
+    keys.sort_unstable();
+
+    let known = known | switch_mask;
+    let branches = keys.iter().map(|&key| {
+        let branch_arms: Vec<&Arm> = arms
+            .iter()
+            .filter(|arm| (arm.value ^ key) & arm.mask & switch_mask == 0)
+            .copied()
+            .collect();
+        let key_lit = make_literal(key, bit_width);
+        let node = generate_tree_node(&branch_arms, known, bit_width, unhandled);
+        quote! { #key_lit => { #node } }
+    });
+
+    // Only arms that don't care about the switched bits can match any other value
+    let default_arms: Vec<&Arm> = arms
+        .iter()
+        .filter(|arm| arm.mask & switch_mask == 0)
+        .copied()
+        .collect();
+    let default = generate_tree_node(&default_arms, known, bit_width, unhandled);
+
+    // Switching on every opcode bit with a branch per value is already exhaustive
+    let full_mask = make_full_mask(bit_width);
+    let default_arm = if switch_mask == full_mask && keys.len() == 1 << bit_width {
+        None
+    } else {
+        Some(quote! { _ => { #default } })
+    };
+
+    let switch_lit = make_literal(switch_mask, bit_width);
+    quote! {
+        match opcode & #switch_lit {
+            #(#branches)*
+            #default_arm
+        }
+    }
+}
+
+/// Generates a decision tree leaf as a linear chain of guarded arms.
 ///
-/// # Returns
-/// Vec of `(opcode_value, bindings)` where bindings is a list of
-/// `(var_name, var_ident, const_value)` tuples.
-fn generate_opcode_variants(
-    pattern: &ParsedPattern,
-    bindings: &[VariableBinding],
-) -> Vec<(u64, Vec<(String, Ident, TokenStream2)>)> {
-    // Collect all variables with their bit positions and binding types
-    let mut var_info: Vec<(&str, u8, u8, BindingValue)> = Vec::new();
+/// Only the bits that haven't been checked by parent nodes are compared.
+fn generate_tree_leaf(
+    arms: &[&Arm],
+    known: u64,
+    bit_width: usize,
+    unhandled: &TokenStream2,
+) -> TokenStream2 {
+    let mut leaf_arms = Vec::new();
+    let mut exhaustive = false;
 
-    for (var_name, &(bit_pos, num_bits)) in &pattern.variables {
-        // Check if this variable has a binding in the where clause
-        if let Some(binding) = bindings.iter().find(|b| &b.name == var_name) {
-            var_info.push((var_name, bit_pos, num_bits, binding.value.clone()));
-        } else {
-            // No binding - use raw bit value as u8 (for primitive type generics)
-            let var_ident = format_ident!("{}", var_name);
-            let raw_value = BindingValue::ConstFn(quote! { #var_ident });
-            var_info.push((var_name, bit_pos, num_bits, raw_value));
+    for arm in arms {
+        let call = &arm.call;
+        let mask = arm.mask & !known;
+        if mask == 0 {
+            // Matches everything that reaches this arm, later arms are unreachable
+            leaf_arms.push(quote! { _ => { #call } });
+            exhaustive = true;
+            break;
         }
+        let mask_lit = make_literal(mask, bit_width);
+        let value_lit = make_literal(arm.value & mask, bit_width);
+        leaf_arms.push(quote! { op if op & #mask_lit == #value_lit => { #call } });
     }
 
-    // Collect phantom variables (bindings that don't correspond to pattern variables)
-    // These are constant expressions that should be added to every variant
-    let mut phantom_bindings: Vec<(String, Ident, TokenStream2)> = Vec::new();
-    for binding in bindings {
-        if !pattern.variables.contains_key(&binding.name) {
-            // This is a phantom variable - evaluate it as a constant
-            let var_ident = format_ident!("{}", binding.name);
-            let value = match &binding.value {
-                BindingValue::Mappings(mappings) => {
-                    // For phantom variables with manual mappings, just use the first variant
-                    // (typically there should only be one for phantom variables)
-                    if mappings.len() != 1 {
-                        panic!(
-                            "Phantom variable '{}' should have exactly one mapping value",
-                            binding.name
-                        );
-                    }
-                    let variant = &mappings[0].variant;
-                    if let Some(enum_type) = &binding.enum_type {
-                        quote! { #enum_type::#variant }
-                    } else {
-                        quote! { #variant }
-                    }
-                }
-                BindingValue::ConstFn(expr) => {
-                    // Const expression - use as-is (no variables to substitute)
-                    expr.clone()
-                }
-            };
-            phantom_bindings.push((binding.name.clone(), var_ident, value));
+    if !exhaustive {
+        leaf_arms.push(quote! { _ => #unhandled });
+    }
+
+    quote! {
+        match opcode {
+            #(#leaf_arms)*
         }
     }
+}
 
-    if var_info.is_empty() {
-        // No pattern variables, just return the base pattern with phantom bindings
-        return vec![(pattern.value, phantom_bindings)];
+/// Handler shims shared by the table-based strategies.
+///
+/// Every distinct handler call gets its own small shim function with a uniform
+/// signature, so it can be stored in a function-pointer table.
+struct Shims<'a> {
+    /// Distinct handler calls, keyed by their token string
+    calls: Vec<(String, &'a TokenStream2)>,
+}
+
+impl<'a> Shims<'a> {
+    fn new() -> Self {
+        Shims { calls: Vec::new() }
     }
 
-    // Recursively generate all combinations of variable values
-    let mut results = Vec::new();
-    generate_combinations(
-        pattern.value,
-        &var_info,
-        0,
-        vec![],
-        HashMap::new(),
-        &mut results,
-    );
+    /// Returns the index of the shim for `call`, creating it if needed
+    fn insert(&mut self, call: &'a TokenStream2) -> usize {
+        let key = call.to_string();
+        match self.calls.iter().position(|(k, _)| *k == key) {
+            Some(index) => index,
+            None => {
+                self.calls.push((key, call));
+                self.calls.len() - 1
+            }
+        }
+    }
 
-    // Add phantom bindings to all results
-    if !phantom_bindings.is_empty() {
-        for (_opcode, bindings_vec) in &mut results {
-            bindings_vec.extend(phantom_bindings.clone());
+    /// The shim function for the call with the given index
+    fn ident(index: usize) -> Ident {
+        format_ident!("__archibald_handler_{}", index)
+    }
+
+    /// Generates a table slot, `None` being left for unhandled opcodes
+    fn slot(slot: Option<usize>) -> TokenStream2 {
+        match slot {
+            Some(index) => {
+                let ident = Self::ident(index);
+                quote! { Some(#ident) }
+            }
+            None => quote! { None },
         }
     }
 
-    results
+    /// Generates all shim functions with the dispatcher's signature
+    fn generate(&self, signature: &Signature, opcode_type: &Type) -> TokenStream2 {
+        let inputs = signature.inputs(&quote! { #opcode_type });
+        let output = signature.output();
+        let shim_fns = self.calls.iter().enumerate().map(|(index, (_, call))| {
+            let ident = Self::ident(index);
+            quote! {
+                fn #ident(#(#inputs),*) #output { #call }
+            }
+        });
+
+        quote! { #(#shim_fns)* }
+    }
 }
 
-/// Holds raw binding information before substitution
-#[derive(Clone)]
-enum RawBinding {
-    /// Manual enum variant mapping
-    Variant(TokenStream2),
-    /// Const function expression that needs variable substitution
-    ConstFnExpr(TokenStream2),
+/// Fills `slots` with the shim of the first arm matching each opcode.
+///
+/// Only the bits in `slot_mask` index the slots, every other bit of the arms is
+/// expected to be resolved by the caller. Slots that are already filled are kept.
+fn fill_slots(slots: &mut [Option<usize>], mask: u64, value: u64, slot_mask: u64, shim: usize) {
+    // Walk every opcode of the arm by enumerating all submasks of the free bits
+    let free = !mask & slot_mask;
+    let mut sub = free;
+    loop {
+        let slot = &mut slots[((value & slot_mask) | sub) as usize];
+        if slot.is_none() {
+            *slot = Some(shim);
+        }
+        if sub == 0 {
+            break;
+        }
+        sub = (sub - 1) & free;
+    }
 }
 
-/// Recursively generates all combinations of variable assignments.
+/// Generates the dispatcher body as a lookup into a static function-pointer table.
 ///
-/// This expands the cartesian product of all possible variable values.
-/// For manual mappings, it uses the explicit variant list.
-/// For const functions, it generates all possible bit values (0 to 2^num_bits - 1).
-fn generate_combinations(
-    current_opcode: u64,
-    var_info: &[(&str, u8, u8, BindingValue)],
-    index: usize,
-    current_bindings: Vec<(String, Ident, RawBinding)>,
-    current_bit_values: HashMap<String, u64>,
-    results: &mut Vec<(u64, Vec<(String, Ident, TokenStream2)>)>,
-) {
-    if index >= var_info.len() {
-        // At the leaf: all variables have been expanded
-        // Now substitute all variables in const function expressions
-        let final_bindings: Vec<(String, Ident, TokenStream2)> = current_bindings
-            .into_iter()
-            .map(|(name, ident, raw_binding)| {
-                let value = match raw_binding {
-                    RawBinding::Variant(v) => v,
-                    RawBinding::ConstFnExpr(expr) => {
-                        // Substitute all variables in the expression
-                        substitute_all_variables(&expr, &current_bit_values)
+/// The table has one slot per opcode, slots are filled in arm order so the first
+/// matching arm wins, just like in the `match` backend. Unmatched slots are empty
+/// and evaluate `unhandled` instead.
+fn generate_table(
+    arms: &[Arm],
+    bit_width: usize,
+    dispatcher: &Dispatcher,
+    opcode_type: &Type,
+    unhandled: &TokenStream2,
+) -> syn::Result<TokenStream2> {
+    let (name, signature) = (&dispatcher.name, &dispatcher.signature);
+    if bit_width > 16 {
+        return Err(syn::Error::new(
+            name.span(),
+            format!(
+                "`strategy = table` of dispatcher '{}' requires an 8 or 16 bit opcode, \
+                 got {} bits",
+                name, bit_width
+            ),
+        ));
+    }
+    if signature.is_generic() {
+        return Err(syn::Error::new(
+            name.span(),
+            format!(
+                "`strategy = table` can't store handlers of the generic dispatcher '{}' in \
+                 a static table, use `match` or `tree` for it: `{}`",
+                name,
+                dispatcher.setting("strategy", "tree")
+            ),
+        ));
+    }
+
+    let table_size = 1usize << bit_width;
+    let full_mask = make_full_mask(bit_width);
+
+    let mut shims = Shims::new();
+    let mut slots: Vec<Option<usize>> = vec![None; table_size];
+    for arm in arms {
+        let shim = shims.insert(&arm.call);
+        fill_slots(&mut slots, arm.mask, arm.value, full_mask, shim);
+    }
+
+    let shim_fns = shims.generate(signature, opcode_type);
+    let handler_type = signature.fn_pointer(opcode_type);
+    let args = signature.args(&quote! { opcode });
+    let entries = slots.iter().map(|&slot| Shims::slot(slot));
+
+    Ok(quote! {
+        #shim_fns
+
+        static __ARCHIBALD_TABLE: [Option<#handler_type>; #table_size] = [
+            #(#entries),*
+        ];
+
+        match __ARCHIBALD_TABLE[opcode as usize] {
+            Some(handler) => handler(#(#args),*),
+            None => #unhandled,
+        }
+    })
+}
+
+/// Generates the dispatcher body as a two-level function-pointer table.
+///
+/// The top `hi` opcode bits index a first-level table. Each of its slots either
+/// points directly to a handler shim, when all opcodes sharing those top bits are
+/// handled the same way, or to a second-level table indexed by the remaining low
+/// bits. Identical second-level tables are emitted only once. Opcodes without a
+/// handler evaluate `unhandled`.
+///
+/// Also returns the total number of table slots across both levels.
+fn generate_two_level(
+    arms: &[Arm],
+    bit_width: usize,
+    hi: usize,
+    dispatcher: &Dispatcher,
+    opcode_type: &Type,
+    unhandled: &TokenStream2,
+) -> syn::Result<(TokenStream2, usize)> {
+    let (name, signature) = (&dispatcher.name, &dispatcher.signature);
+    let lo = bit_width.saturating_sub(hi);
+    if hi == 0 || hi >= bit_width || hi > 16 || lo > 16 {
+        return Err(syn::Error::new(
+            name.span(),
+            format!(
+                "`strategy = two_level(hi = {})` of dispatcher '{}' needs 1 to 16 top bits \
+                 and at most 16 remaining bits for a {} bit opcode",
+                hi, name, bit_width
+            ),
+        ));
+    }
+    if signature.is_generic() {
+        return Err(syn::Error::new(
+            name.span(),
+            format!(
+                "`strategy = two_level` can't store handlers of the generic dispatcher '{}' \
+                 in a static table, use `match` or `tree` for it: `{}`",
+                name,
+                dispatcher.setting("strategy", "tree")
+            ),
+        ));
+    }
+
+    let lo_mask = (1u64 << lo) - 1;
+    let hi_size = 1usize << hi;
+    let lo_size = 1usize << lo;
+
+    // Collect the arms reachable from every first-level slot, in priority order.
+    // A slot stops collecting once an arm covers all of its low bits.
+    let mut shims = Shims::new();
+    let mut reachable: Vec<Vec<&Arm>> = vec![Vec::new(); hi_size];
+    for arm in arms {
+        let hi_fixed = (arm.mask >> lo) & (hi_size as u64 - 1);
+        let free = !hi_fixed & (hi_size as u64 - 1);
+        let mut sub = free;
+        loop {
+            let index = (((arm.value >> lo) & hi_fixed) | sub) as usize;
+            let slot_arms = &mut reachable[index];
+            if slot_arms.last().is_none_or(|last| last.mask & lo_mask != 0) {
+                slot_arms.push(arm);
+            }
+            if sub == 0 {
+                break;
+            }
+            sub = (sub - 1) & free;
+        }
+    }
+
+    // Build the first level, deduplicating second-level tables by their content
+    let mut sub_tables: Vec<Vec<Option<usize>>> = Vec::new();
+    let mut first_level = Vec::with_capacity(hi_size);
+    for slot_arms in &reachable {
+        let entry = match slot_arms.first() {
+            None => quote! { __ArchibaldSlot::Unhandled },
+            Some(arm) if arm.mask & lo_mask == 0 => {
+                let shim = Shims::ident(shims.insert(&arm.call));
+                quote! { __ArchibaldSlot::Handler(#shim) }
+            }
+            Some(_) => {
+                let mut slots: Vec<Option<usize>> = vec![None; lo_size];
+                for arm in slot_arms {
+                    let shim = shims.insert(&arm.call);
+                    fill_slots(&mut slots, arm.mask, arm.value, lo_mask, shim);
+                }
+                let index = match sub_tables.iter().position(|table| *table == slots) {
+                    Some(index) => index,
+                    None => {
+                        sub_tables.push(slots);
+                        sub_tables.len() - 1
                     }
                 };
-                (name, ident, value)
-            })
-            .collect();
-
-        results.push((current_opcode, final_bindings));
-        return;
+                let table = format_ident!("__ARCHIBALD_TABLE_{}", index);
+                quote! { __ArchibaldSlot::Table(&#table) }
+            }
+        };
+        first_level.push(entry);
     }
 
-    let (var_name, bit_pos, num_bits, binding_value) = &var_info[index];
+    let shim_fns = shims.generate(signature, opcode_type);
+    let handler_type = signature.fn_pointer(opcode_type);
+    let args = signature.args(&quote! { opcode });
+    let sub_table_statics = sub_tables.iter().enumerate().map(|(index, slots)| {
+        let table = format_ident!("__ARCHIBALD_TABLE_{}", index);
+        let entries = slots.iter().map(|&slot| Shims::slot(slot));
+        quote! {
+            static #table: [Option<#handler_type>; #lo_size] = [
+                #(#entries),*
+            ];
+        }
+    });
+
+    let lo_lit = proc_macro2::Literal::usize_unsuffixed(lo);
+    let lo_mask_lit = make_literal(lo_mask, bit_width);
+
+    let table_slots = hi_size + sub_tables.len() * lo_size;
+    let body = quote! {
+        #shim_fns
+
+        #[derive(Clone, Copy)]
+        enum __ArchibaldSlot {
+            Handler(#handler_type),
+            Table(&'static [Option<#handler_type>; #lo_size]),
+            Unhandled,
+        }
+
+        #(#sub_table_statics)*
+
+        static __ARCHIBALD_TABLE: [__ArchibaldSlot; #hi_size] = [
+            #(#first_level),*
+        ];
+
+        match __ARCHIBALD_TABLE[(opcode >> #lo_lit) as usize] {
+            __ArchibaldSlot::Handler(handler) => handler(#(#args),*),
+            __ArchibaldSlot::Table(table) => match table[(opcode & #lo_mask_lit) as usize] {
+                Some(handler) => handler(#(#args),*),
+                None => #unhandled,
+            },
+            __ArchibaldSlot::Unhandled => #unhandled,
+        }
+    };
+
+    Ok((body, table_slots))
+}
 
-    match binding_value {
-        BindingValue::Mappings(mappings) => {
-            // Manual mapping: expand all bit patterns
-            for mapping in mappings {
-                let bits_value =
-                    u64::from_str_radix(&mapping.bits, 2).expect("Invalid binary string");
-                let new_opcode = current_opcode | (bits_value << bit_pos);
+/// `strategy = auto` keeps the plain `match` for tables with at most this many arms.
+const AUTO_MATCH_ARMS: usize = 8;
 
-                let mut new_bindings = current_bindings.clone();
-                let variant = &mapping.variant;
-                new_bindings.push((
-                    var_name.to_string(),
-                    format_ident!("{}", var_name),
-                    RawBinding::Variant(quote! { #variant }),
-                ));
+/// `strategy = auto` picks a two-level table for u16 tables covering at least this
+/// share of the opcode space, and a decision tree for sparser ones.
+const AUTO_TWO_LEVEL_COVERAGE: f64 = 0.25;
 
-                let mut new_bit_values = current_bit_values.clone();
-                new_bit_values.insert(var_name.to_string(), bits_value);
+/// Number of top bits used when `strategy = auto` picks a two-level table.
+const AUTO_TWO_LEVEL_HI: usize = 8;
 
-                generate_combinations(
-                    new_opcode,
-                    var_info,
-                    index + 1,
-                    new_bindings,
-                    new_bit_values,
-                    results,
-                );
-            }
-        }
-        BindingValue::ConstFn(fn_expr) => {
-            // Const function: expand all possible bit values
-            let max_value = (1u64 << num_bits) - 1;
-            for bits_value in 0..=max_value {
-                let new_opcode = current_opcode | (bits_value << bit_pos);
+/// Counts the opcodes matched by at least one arm, for opcodes of up to 16 bits.
+fn count_covered(arms: &[Arm], bit_width: usize) -> Option<usize> {
+    if bit_width > 16 {
+        return None;
+    }
 
-                let mut new_bindings = current_bindings.clone();
-                let var_ident = format_ident!("{}", var_name);
+    let full_mask = make_full_mask(bit_width);
+    let mut slots: Vec<Option<usize>> = vec![None; 1 << bit_width];
+    for arm in arms {
+        fill_slots(&mut slots, arm.mask, arm.value, full_mask, 0);
+    }
+    Some(slots.iter().filter(|slot| slot.is_some()).count())
+}
 
-                // Store the raw expression - we'll substitute later when we have all bit values
-                new_bindings.push((
-                    var_name.to_string(),
-                    var_ident,
-                    RawBinding::ConstFnExpr(fn_expr.clone()),
-                ));
+/// Picks a concrete strategy for `strategy = auto`, along with the reason for it.
+///
+/// - Short tables and tables of exact opcodes stay a `match`, which the compiler
+///   already lowers to a jump table or a short compare chain
+/// - u8 tables use a single function-pointer table
+/// - Dense u16 tables use a two-level table, sparse ones a decision tree
+/// - Wider tables, and dispatchers with generic parameters that can't be stored in
+///   function-pointer tables, always use a decision tree
+fn select_strategy(
+    arms: &[Arm],
+    bit_width: usize,
+    covered: Option<usize>,
+    generic: bool,
+) -> (Strategy, String) {
+    let full_mask = make_full_mask(bit_width);
+    let guarded = arms.iter().filter(|arm| arm.mask != full_mask).count();
 
-                let mut new_bit_values = current_bit_values.clone();
-                new_bit_values.insert(var_name.to_string(), bits_value);
+    if arms.len() <= AUTO_MATCH_ARMS {
+        return (
+            Strategy::Match,
+            format!("only {} arms, a compare chain is cheap", arms.len()),
+        );
+    }
+    if guarded == 0 {
+        return (
+            Strategy::Match,
+            "all arms match exact opcodes, which compile to a jump table".to_string(),
+        );
+    }
+    if generic {
+        return (
+            Strategy::Tree,
+            "handlers of a generic dispatcher can't be stored in static tables".to_string(),
+        );
+    }
 
-                generate_combinations(
-                    new_opcode,
-                    var_info,
-                    index + 1,
-                    new_bindings,
-                    new_bit_values,
-                    results,
-                );
+    match (bit_width, covered) {
+        (8, _) => (
+            Strategy::Table,
+            format!(
+                "{} guarded arms over 256 opcodes fit a single table",
+                guarded
+            ),
+        ),
+        (16, Some(covered)) => {
+            let coverage = covered as f64 / (1 << bit_width) as f64;
+            if coverage >= AUTO_TWO_LEVEL_COVERAGE {
+                (
+                    Strategy::TwoLevel {
+                        hi: AUTO_TWO_LEVEL_HI,
+                    },
+                    format!(
+                        "{:.1}% of the 16 bit opcode space is covered, a full table is too big",
+                        coverage * 100.0
+                    ),
+                )
+            } else {
+                (
+                    Strategy::Tree,
+                    format!(
+                        "only {:.1}% of the 16 bit opcode space is covered",
+                        coverage * 100.0
+                    ),
+                )
             }
         }
+        _ => (
+            Strategy::Tree,
+            format!("{} bit opcodes are too wide for tables", bit_width),
+        ),
     }
 }
 
-/// Substitutes multiple variables in a token stream.
-///
-/// Used for const functions that reference multiple variables like `decode_mode(m, x)`.
-/// This replaces all variable identifiers with their concrete bit values.
+/// Coverage of wide tables is analyzed symbolically while the unhandled opcodes fit
+/// in this many disjoint cubes, and estimated from a sample otherwise.
+const COVERAGE_MAX_PIECES: usize = 1 << 16;
+
+/// Number of opcodes sampled when the coverage can't be analyzed symbolically.
+const COVERAGE_SAMPLES: usize = 1 << 16;
+
+/// Unhandled opcodes listed at most in errors of `coverage = require`.
+const COVERAGE_LISTED: usize = 8;
+
+/// The opcodes no arm handles.
+struct Unhandled {
+    /// Disjoint cubes of unhandled opcodes. Only the sampled unhandled opcodes if the
+    /// analysis isn't exact
+    cubes: Vec<Cube>,
+    /// Number of unhandled opcodes, estimated if the analysis isn't exact
+    count: u128,
+    /// Whether the cubes and count are exact or estimated from a sample
+    exact: bool,
+}
+
+/// Finds the opcodes that fall through to the unhandled opcode handler.
 ///
-/// # Arguments
-/// * `tokens` - The token stream to process (e.g., the const function call)
-/// * `var_bit_values` - Map of variable names to their bit values
-fn substitute_all_variables(
-    tokens: &TokenStream2,
-    var_bit_values: &HashMap<String, u64>,
-) -> TokenStream2 {
-    let mut result = TokenStream2::new();
+/// 8 and 16 bit tables are checked opcode by opcode. Wider tables subtract the arms
+/// from the whole opcode space, and fall back to sampling opcodes if the remainder
+/// gets too fragmented.
+fn find_unhandled(arms: &[Arm], bit_width: usize) -> Unhandled {
+    let full_mask = make_full_mask(bit_width);
 
-    for tt in tokens.clone().into_iter() {
-        match tt {
-            proc_macro2::TokenTree::Ident(ref ident) => {
-                let ident_str = ident.to_string();
-                if let Some(&bit_value) = var_bit_values.get(&ident_str) {
-                    // Replace variable with bit value
-                    let bit_lit = proc_macro2::Literal::u8_suffixed(bit_value as u8);
-                    result.extend(std::iter::once(proc_macro2::TokenTree::Literal(bit_lit)));
-                } else {
-                    // Keep the identifier as-is
-                    result.extend(std::iter::once(tt));
-                }
-            }
-            proc_macro2::TokenTree::Group(group) => {
-                // Recursively process grouped tokens
-                let inner = substitute_all_variables(&group.stream(), var_bit_values);
-                let new_group = proc_macro2::Group::new(group.delimiter(), inner);
-                result.extend(std::iter::once(proc_macro2::TokenTree::Group(new_group)));
-            }
-            _ => {
-                result.extend(std::iter::once(tt));
-            }
+    if bit_width <= 16 {
+        let mut slots: Vec<Option<usize>> = vec![None; 1 << bit_width];
+        for arm in arms {
+            fill_slots(&mut slots, arm.mask, arm.value, full_mask, 0);
         }
+        let unhandled: Vec<Cube> = (0..slots.len() as u64)
+            .filter(|&opcode| slots[opcode as usize].is_none())
+            .map(|opcode| (full_mask, opcode))
+            .collect();
+        return Unhandled {
+            count: unhandled.len() as u128,
+            cubes: merge_cubes(unhandled, bit_width),
+            exact: true,
+        };
     }
 
-    result
+    // Subtract the largest cubes first, they fragment the remainder the least
+    let mut cubes: Vec<Cube> = arms.iter().map(|arm| (arm.mask, arm.value)).collect();
+    cubes.sort_unstable();
+    cubes.dedup();
+    let mut cubes = merge_cubes(cubes, bit_width);
+    cubes.sort_by_key(|cube| cube.0.count_ones());
+
+    let mut pieces: Vec<Cube> = vec![(0, 0)];
+    for &cut in &cubes {
+        let mut remaining = Vec::with_capacity(pieces.len());
+        for &piece in &pieces {
+            subtract_cube(piece, cut, &mut remaining);
+        }
+        pieces = remaining;
+        if pieces.len() > COVERAGE_MAX_PIECES {
+            return sample_unhandled(arms, bit_width);
+        }
+    }
+
+    Unhandled {
+        count: pieces
+            .iter()
+            .map(|&piece| cube_size(piece, bit_width))
+            .sum(),
+        cubes: pieces,
+        exact: true,
+    }
 }
 
-fn make_literal(value: u64, bit_width: usize) -> proc_macro2::Literal {
-    match bit_width {
-        8 => proc_macro2::Literal::u8_suffixed(value as u8),
-        16 => proc_macro2::Literal::u16_suffixed(value as u16),
-        32 => proc_macro2::Literal::u32_suffixed(value as u32),
-        64 => proc_macro2::Literal::u64_suffixed(value),
-        _ => panic!("Unsupported bit width: {}", bit_width),
+/// Estimates the unhandled opcodes from a deterministic sample of the opcode space.
+fn sample_unhandled(arms: &[Arm], bit_width: usize) -> Unhandled {
+    let full_mask = make_full_mask(bit_width);
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut unhandled = Vec::new();
+
+    for _ in 0..COVERAGE_SAMPLES {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let opcode = state & full_mask;
+        if !arms.iter().any(|arm| opcode & arm.mask == arm.value) {
+            unhandled.push((full_mask, opcode));
+        }
+    }
+
+    let count = (1u128 << bit_width) * unhandled.len() as u128 / COVERAGE_SAMPLES as u128;
+    unhandled.sort_unstable();
+    unhandled.dedup();
+    Unhandled {
+        cubes: unhandled,
+        count,
+        exact: false,
     }
 }
 
-fn make_full_mask(bit_width: usize) -> u64 {
-    match bit_width {
-        8 => 0xFF,
-        16 => 0xFFFF,
-        32 => 0xFFFF_FFFF,
-        64 => 0xFFFF_FFFF_FFFF_FFFF,
-        _ => panic!("Unsupported bit width: {}", bit_width),
+/// Writes a cube as a pattern string, e.g. `"0000'1___"`.
+fn cube_pattern(cube: Cube, bit_width: usize) -> String {
+    let mut pattern = String::new();
+    for bit in (0..bit_width).rev() {
+        let bit_mask = 1u64 << bit;
+        pattern.push(match (cube.0 & bit_mask != 0, cube.1 & bit_mask != 0) {
+            (false, _) => '_',
+            (true, false) => '0',
+            (true, true) => '1',
+        });
+        if bit % 4 == 0 && bit != 0 {
+            pattern.push('\'');
+        }
     }
+    format!("\"{}\"", pattern)
 }
 
-/// Generates a handler function call with const generic arguments.
+/// Generates the constants for `coverage = report` and `coverage = require`.
 ///
-/// Looks up variable values from bindings and wraps them appropriately:
-/// - Enum variants: Adds type prefix (e.g., `Register::R0`)
-/// - Const fn results: Wraps in braces (e.g., `{ decode_mode(0u8) }`)
-/// - Primitive values: Wraps in braces (e.g., `{ 0u8 }`)
-fn generate_handler_call(
-    handler: &HandlerSpec,
-    bindings: &[(String, Ident, TokenStream2)],
-    where_clause: &Option<WhereClause>,
-    handler_opcode_type: &Option<Type>,
-) -> TokenStream2 {
-    let handler_name = &handler.name;
+/// With `require`, a table that doesn't handle every opcode is an error pointing at
+/// the coverage mode, listing some of the unhandled opcodes.
+fn generate_coverage(
+    coverage: Coverage,
+    span: Span,
+    arms: &[Arm],
+    bit_width: usize,
+    opcode_type: &Type,
+) -> syn::Result<TokenStream2> {
+    let unhandled = find_unhandled(arms, bit_width);
 
-    let generic_args: Vec<TokenStream2> = handler
-        .generics
-        .iter()
-        .map(|var_name| {
-            // Find the const value for this variable
-            let value = bindings
-                .iter()
-                .find(|(name, _, _)| name == var_name)
-                .map(|(_, _, value)| value)
-                .unwrap_or_else(|| {
-                    panic!("Variable '{}' not found in where clause bindings", var_name)
-                });
+    if coverage == Coverage::Require && (unhandled.count > 0 || !unhandled.exact) {
+        let mut listed: Vec<String> = unhandled
+            .cubes
+            .iter()
+            .take(COVERAGE_LISTED)
+            .map(|&cube| cube_pattern(cube, bit_width))
+            .collect();
+        if unhandled.cubes.len() > COVERAGE_LISTED {
+            listed.push("...".to_string());
+        }
+        let message = match (unhandled.exact, listed.is_empty()) {
+            (true, _) => format!(
+                "Table is not exhaustive, {} opcodes are unhandled: {}",
+                unhandled.count,
+                listed.join(", ")
+            ),
+            (false, false) => format!(
+                "Table is not exhaustive, unhandled opcodes include: {}",
+                listed.join(", ")
+            ),
+            (false, true) => "Table is too fragmented to prove that it is exhaustive".to_string(),
+        };
+        return Err(syn::Error::new(span, message));
+    }
 
-            // Look up the type annotation from the where clause
-            let enum_type = where_clause.as_ref().and_then(|wc| {
-                wc.bindings
-                    .iter()
-                    .find(|b| &b.name == var_name)
-                    .and_then(|b| b.enum_type.as_ref())
-            });
+    let total = 1u128 << bit_width;
+    let percent = (total - unhandled.count) as f64 * 100.0 / total as f64;
+    let percent_doc = if unhandled.exact {
+        " Percentage of opcodes handled by the table."
+    } else {
+        " Percentage of opcodes handled by the table, estimated from a sample."
+    };
 
-            // Check if the value is a simple identifier (enum variant)
-            let value_str = value.to_string();
-            if value_str.chars().all(|c| c.is_alphanumeric() || c == '_') {
-                // Simple identifier - could be an enum variant
-                if let Some(enum_type) = enum_type {
-                    // Add enum type prefix for variants
-                    quote! { { #enum_type::#value } }
-                } else {
-                    // Primitive value or untyped
-                    quote! { { #value } }
+    let patterns = unhandled.cubes.iter().map(|&(mask, value)| {
+        let mask = make_literal(mask, bit_width);
+        let value = make_literal(value, bit_width);
+        quote! { (#mask, #value) }
+    });
+    let patterns_doc = if unhandled.exact {
+        " Opcodes not handled by any entry, as `(mask, value)` pairs matching `opcode & mask == value`."
+    } else {
+        " Sampled opcodes not handled by any entry, as `(mask, value)` pairs matching `opcode & mask == value`."
+    };
+
+    // Listing every single opcode only makes sense for small opcode spaces
+    let opcodes = (bit_width <= 16).then(|| {
+        let opcodes = unhandled.cubes.iter().flat_map(|&(mask, value)| {
+            let free = !mask & make_full_mask(bit_width);
+            let mut sub = free;
+            let mut opcodes = Vec::new();
+            loop {
+                opcodes.push(value | sub);
+                if sub == 0 {
+                    break;
                 }
-            } else {
-                // Complex expression (const function call) - already complete
-                quote! { { #value } }
+                sub = (sub - 1) & free;
+            }
+            opcodes
+        });
+        let mut opcodes: Vec<u64> = opcodes.collect();
+        opcodes.sort_unstable();
+        let opcodes = opcodes
+            .into_iter()
+            .map(|opcode| make_literal(opcode, bit_width));
+        quote! {
+            #[doc = " Opcodes not handled by any entry, in ascending order."]
+            pub const UNHANDLED_OPCODES: &[#opcode_type] = &[#(#opcodes),*];
+        }
+    });
+
+    Ok(quote! {
+        #[doc = #percent_doc]
+        pub const COVERAGE: f64 = #percent;
+        #[doc = #patterns_doc]
+        pub const UNHANDLED_PATTERNS: &[(#opcode_type, #opcode_type)] = &[#(#patterns),*];
+        #opcodes
+    })
+}
+
+/// Builds the human readable report emitted for `strategy = auto`.
+fn strategy_report(
+    strategy: Strategy,
+    reason: &str,
+    arms: &[Arm],
+    bit_width: usize,
+    covered: Option<usize>,
+    table_slots: usize,
+) -> String {
+    let full_mask = make_full_mask(bit_width);
+    let guarded = arms.iter().filter(|arm| arm.mask != full_mask).count();
+
+    let mut instantiations: Vec<String> = arms.iter().map(|arm| arm.call.to_string()).collect();
+    instantiations.sort_unstable();
+    instantiations.dedup();
+
+    let covered = match covered {
+        Some(covered) => format!("{} of {}", covered, 1u64 << bit_width),
+        None => "not computed for wide opcodes".to_string(),
+    };
+
+    format!(
+        "strategy: {} ({})\n\
+         arms: {} ({} guarded)\n\
+         handler instantiations: {}\n\
+         opcodes covered: {}\n\
+         table size: {} slots",
+        strategy,
+        reason,
+        arms.len(),
+        guarded,
+        instantiations.len(),
+        covered,
+        table_slots
+    )
+}
+
+/// Generates a dispatcher function, and its `try_<dispatcher>` and strategy report if
+/// enabled, from its arms.
+fn generate_dispatcher(
+    table: &InstructionTable,
+    dispatcher: &Dispatcher,
+    arms: &[Arm],
+    bit_width: usize,
+) -> syn::Result<TokenStream2> {
+    let opcode_type = &table.opcode_type;
+    let dispatcher_name = &dispatcher.name;
+    let signature = &dispatcher.signature;
+
+    // Resolve `strategy = auto` into a concrete backend
+    let (strategy, reason) = match dispatcher.strategy {
+        Strategy::Auto => {
+            let covered = count_covered(arms, bit_width);
+            let (strategy, reason) =
+                select_strategy(arms, bit_width, covered, signature.is_generic());
+            (strategy, Some((reason, covered)))
+        }
+        strategy => (strategy, None),
+    };
+
+    let unhandled = unhandled_opcode(table, dispatcher);
+    let inputs = signature.inputs(&quote! { impl Into<#opcode_type> });
+    let output = signature.output();
+    let (impl_generics, _, where_clause) = signature.generics.split_for_impl();
+    let result_type = match &signature.output {
+        Some(return_type) => quote! { #return_type },
+        None => quote! { () },
+    };
+    // The error type of the `try_<dispatcher>`, e.g. `DispatchUnhandled`
+    let unhandled_type = format_ident!(
+        "{}Unhandled",
+        upper_camel_case(&dispatcher_name.to_string())
+    );
+
+    // The body of the dispatcher evaluating `unhandled` for opcodes that no entry
+    // handles, along with the number of table slots it uses
+    let generate_body = |unhandled: &TokenStream2| -> syn::Result<(TokenStream2, usize)> {
+        Ok(match strategy {
+            Strategy::Match | Strategy::Auto => (generate_match(arms, bit_width, unhandled), 0),
+            Strategy::Tree => (generate_tree(arms, bit_width, unhandled), 0),
+            Strategy::Table => {
+                let body = generate_table(arms, bit_width, dispatcher, opcode_type, unhandled)?;
+                (body, 1 << bit_width)
+            }
+            Strategy::TwoLevel { hi } => {
+                generate_two_level(arms, bit_width, hi, dispatcher, opcode_type, unhandled)?
             }
         })
-        .collect();
+    };
+    let (body, table_slots) = generate_body(&unhandled)?;
 
-    // Convert opcode if handler_opcode_type is specified
-    let opcode_arg = if let Some(handler_type) = handler_opcode_type {
-        quote! { #handler_type(opcode) }
-    } else {
-        quote! { opcode }
+    // Explain the choice made by `strategy = auto`, both as a constant and in the docs
+    let (report_const, report_doc) = match reason {
+        Some((reason, covered)) => {
+            let report = strategy_report(strategy, &reason, arms, bit_width, covered, table_slots);
+            let doc_lines = report.lines().map(|line| format!(" - {}", line));
+            let doc = quote! {
+                #[doc = " Dispatch strategy selected by `strategy = auto`:"]
+                #[doc = ""]
+                #(#[doc = #doc_lines])*
+            };
+            // Labelled dispatchers prefix the constant with their label
+            let report_name = match &dispatcher.label {
+                Some(label) => {
+                    format_ident!("{}_STRATEGY_REPORT", label.to_string().to_uppercase())
+                }
+                None => format_ident!("STRATEGY_REPORT"),
+            };
+            let report_const = quote! {
+                #doc
+                pub const #report_name: &str = #report;
+            };
+            (Some(report_const), Some(doc))
+        }
+        None => (None, None),
     };
 
-    if generic_args.is_empty() {
-        quote! { #handler_name(ctx, #opcode_arg) }
+    // Generate the dispatcher function
+    let dispatcher_fn = quote! {
+        #report_doc
+        #[inline]
+        pub fn #dispatcher_name #impl_generics (#(#inputs),*) #output #where_clause {
+            let opcode = opcode.into();
+            #body
+        }
+    };
+    // The `try_<dispatcher>` has its own body returning unhandled opcodes as errors,
+    // the dispatcher doesn't call it so parameters passed by value are only moved once
+    let dispatchers = if dispatcher.try_dispatcher {
+        let (try_body, _) = generate_body(&quote! {
+            return ::core::result::Result::Err(#unhandled_type(opcode))
+        })?;
+        let try_name = format_ident!("try_{}", dispatcher_name);
+        let try_doc = format!(
+            " Like [`{}`], but returns opcodes that no entry handles as an error.",
+            dispatcher_name
+        );
+        let unhandled_doc = format!(
+            " An opcode that no entry of [`{}`] handles, returned by [`{}`].",
+            dispatcher_name, try_name
+        );
+        quote! {
+            #[doc = #unhandled_doc]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+            pub struct #unhandled_type(pub #opcode_type);
+
+            impl ::core::fmt::Display for #unhandled_type {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    write!(f, "Unhandled opcode: 0x{:02X}", self.0)
+                }
+            }
+
+            impl ::core::error::Error for #unhandled_type {}
+
+            #[doc = #try_doc]
+            #[inline]
+            pub fn #try_name #impl_generics (
+                #(#inputs),*
+            ) -> ::core::result::Result<#result_type, #unhandled_type> #where_clause {
+                let opcode = opcode.into();
+                ::core::result::Result::Ok({ #try_body })
+            }
+
+            #dispatcher_fn
+        }
     } else {
-        quote! { #handler_name::<#(#generic_args),*>(ctx, #opcode_arg) }
-    }
+        dispatcher_fn
+    };
+
+    Ok(quote! {
+        #report_const
+        #dispatchers
+    })
 }
 
 /// The main procedural macro for generating instruction decoders.
@@ -923,12 +5268,36 @@ fn generate_handler_call(
 /// instruction_table! {
 ///     type Opcode = u8;           // Opcode type (u8, u16, u32, or u64)
 ///
-///     dispatcher = dispatch;      // Name of generated function
-///     context = Cpu;              // Type passed to handlers
+///     dispatcher = dispatch;      // Name of generated function, optionally with
+///                                 // generics: `dispatch<B: Bus> where B: Clone`,
+///                                 // or one of several: `dispatcher exec = execute in ops;`
+///     context = Cpu;              // Type passed to handlers, or a full signature:
+///                                 // `signature = fn(cpu: &mut Cpu, opcode) -> u32;`
+///     strategy = tree;            // Optional: `match` (default), `table`, `tree`
+///                                 // `two_level(hi = N)` or `auto`
+///     coverage = report;          // Optional: `report` or `require` unhandled opcodes
+///     priority = specificity;     // Optional: `declaration` (default) or `specificity`
+///     returns = u32;              // Optional: type returned by handlers
+///     handlers = method;          // Optional: `function` (default) or `method`
+///     handler_trait = Handlers;   // Optional: generate a trait declaring the handlers,
+///                                 // replaces `context`
+///     context exec = Cpu;         // Optional: setting of the dispatcher labelled `exec`,
+///                                 // also `strategy exec = table;`
+///     decoder = decode -> Insn;   // Optional: generate `decode` and `enum Insn`
+///     encoder = encode;           // Optional: generate `Insn::encode`
+///     disassembler = disasm;      // Optional: name of the disassembler
+///     fallback = illegal;         // Optional: handler or value for unhandled opcodes
+///     try_dispatcher = true;      // Optional: also generate `try_dispatch`
+///
+///     format F = "{op:4}{var:4}"; // Optional: reusable instruction formats
 ///
 ///     "pattern" => handler<{var}> where {
 ///         var: Type = mapping_or_const_fn
 ///     };
+///     F(op = 0b0001) => handler<{var}>;
+///     "0010'____" => self.method;            // Method of the first parameter
+///     "0001'{imm:4}" => handler(imm as i8);  // Runtime field as extra argument
+///     "0011'{imm:4}" => handler(imm) as "op #{imm}"; // Syntax for the disassembler
 ///     ...
 /// }
 /// ```
@@ -937,153 +5306,181 @@ fn generate_handler_call(
 ///
 /// Generates a function with signature:
 /// ```ignore
-/// pub fn dispatcher_name(ctx: &mut ContextType, opcode: OpcodeType)
+/// pub fn dispatcher_name(ctx: &mut ContextType, opcode: impl Into<OpcodeType>) -> ReturnType
 /// ```
 ///
-/// The function contains a match expression that:
-/// - Matches exact opcodes for patterns without wildcards
-/// - Uses masked matching (`op & mask == value`) for patterns with wildcards
-/// - Calls handlers with const generic arguments based on variable bindings
-/// - Panics on unhandled opcodes
+/// or the parameters of the `signature`, with the opcode as `impl Into<OpcodeType>`.
+///
+/// With the default `match` strategy, the function contains a match expression that:
+/// - Matches exact opcodes, ranges and or-patterns (`a | b | lo..=hi`), merging
+///   opcodes that call the same handler instantiation where priority allows it
+/// - Uses masked matching (`op & mask == value`) for patterns with many scattered
+///   wildcard bits
+/// - Calls handlers with const generic arguments based on variable bindings, followed
+///   by the runtime fields extracted from the opcode
+/// - Panics on unhandled opcodes, or evaluates the `fallback`
+///
+/// With `strategy = table`, the opcode indexes a static function-pointer table
+/// instead, with empty slots for unhandled opcodes. With
+/// `strategy = tree`, the arms are split into a decision tree of nested matches.
+/// With `strategy = two_level(hi = N)`, the top `N` bits index a first-level table
+/// of handlers and shared second-level tables. With `strategy = auto`, one of these
+/// is picked for you and a `pub const STRATEGY_REPORT: &str` explaining the choice
+/// is generated next to the dispatcher.
 #[proc_macro]
 pub fn instruction_table(input: TokenStream) -> TokenStream {
     let table = parse_macro_input!(input as InstructionTable);
 
     let opcode_type = &table.opcode_type;
-    let dispatcher_name = &table.dispatcher_name;
-    let context_type = &table.context_type;
 
-    // Generate match arms for all instruction patterns
-    let mut match_arms = Vec::new();
-    let mut seen_patterns: Vec<(u64, u64)> = Vec::new(); // Deduplication: (mask, value) pairs
+    // Problems are collected across all formats and entries and reported together
+    let mut errors = Errors::default();
 
-    for entry in &table.entries {
-        let pattern = parse_pattern(&entry.pattern);
-        let bit_width = pattern.bit_width;
-        let bindings = entry
-            .where_clause
-            .as_ref()
-            .map(|wc| wc.bindings.as_slice())
-            .unwrap_or(&[]);
+    // Parse every format once, entries using an invalid format are skipped
+    let formats: Vec<(&Ident, Option<ParsedPattern>)> = table
+        .formats
+        .iter()
+        .map(|format| (&format.name, errors.check(parse_pattern(&format.pattern))))
+        .collect();
+    let mut patterns: Vec<Option<ParsedPattern>> = table
+        .entries
+        .iter()
+        .map(|entry| {
+            errors
+                .check(resolve_pattern(&entry.pattern, &formats))
+                .flatten()
+        })
+        .collect();
 
-        // Determine if we need to expand variables into multiple match arms
-        // Variables with where clause bindings get expanded (e.g., r: Register = { ... })
-        // Variables without bindings are passed as raw u8 values
-        let has_expandable_vars = bindings
-            .iter()
-            .any(|b| pattern.variables.contains_key(&b.name));
-
-        let has_any_vars = !pattern.variables.is_empty();
-
-        if has_expandable_vars || has_any_vars {
-            // Expand pattern into all variable combinations
-            // E.g., "00rr" with r=2bits generates 4 opcodes: 0x00, 0x01, 0x02, 0x03
-            let variants = generate_opcode_variants(&pattern, bindings);
-
-            // Calculate the full mask including expanded variable bits
-            // This is used for wildcard patterns to create guard expressions
-            let mut expanded_mask = pattern.mask;
-            for binding in bindings {
-                if let Some(&(bit_pos, num_bits)) = pattern.variables.get(&binding.name) {
-                    // Mark variable bits as fixed in the mask since we expanded them
-                    for i in 0..num_bits {
-                        expanded_mask |= 1 << (bit_pos + i);
-                    }
-                }
-            }
+    // All patterns must be as wide as the opcode type
+    let bit_width = opcode_bit_width(opcode_type)
+        .or_else(|| {
+            patterns
+                .iter()
+                .flatten()
+                .next()
+                .map(|pattern| pattern.bit_width)
+        })
+        .unwrap_or(8);
+    for (entry, pattern) in table.entries.iter().zip(&mut patterns) {
+        if let Some(width) = pattern.as_ref().map(|pattern| pattern.bit_width)
+            && width != bit_width
+        {
+            errors.push(syn::Error::new(
+                entry.pattern.span(),
+                format!(
+                    "Pattern has {} bits, but opcodes have {} bits",
+                    width, bit_width
+                ),
+            ));
+            *pattern = None;
+        }
+    }
 
-            for (opcode, var_bindings) in variants {
-                if pattern.wildcard_bits != 0 {
-                    // Pattern has wildcards - use guard-based match
-                    // E.g., "11rr'____" with r=R0 matches 0b1100_0000..0b1100_1111
-                    let handler_call = generate_handler_call(
-                        &entry.handler,
-                        &var_bindings,
-                        &entry.where_clause,
-                        &table.handler_opcode_type,
-                    );
-
-                    // Skip if already covered by an exact match
-                    let full_mask = make_full_mask(bit_width);
-                    let dominated = seen_patterns
-                        .iter()
-                        .any(|(m, v)| *m == full_mask && *v == opcode);
-
-                    if !dominated {
-                        let mask_lit = make_literal(expanded_mask, bit_width);
-                        let value_lit = make_literal(opcode, bit_width);
-                        match_arms.push(quote! {
-                            op if op & #mask_lit == #value_lit => { #handler_call }
-                        });
-                        seen_patterns.push((expanded_mask, opcode));
-                    }
-                } else {
-                    // No wildcards - exact opcode match
-                    let full_mask = make_full_mask(bit_width);
-                    let key = (full_mask, opcode);
-                    if !seen_patterns.contains(&key) {
-                        let handler_call = generate_handler_call(
-                            &entry.handler,
-                            &var_bindings,
-                            &entry.where_clause,
-                            &table.handler_opcode_type,
-                        );
-                        let opcode_lit = make_literal(opcode, bit_width);
-                        match_arms.push(quote! {
-                            #opcode_lit => { #handler_call }
-                        });
-                        seen_patterns.push(key);
-                    }
+    for dispatcher in &table.dispatchers {
+        errors.check(validate_methods(&table, dispatcher));
+    }
+
+    // Semantic checks of every entry, invalid entries are left out of code generation
+    for (entry, pattern) in table.entries.iter().zip(&mut patterns) {
+        if let Some(valid) = pattern.as_ref()
+            && errors.check(validate_entry(entry, valid)).is_none()
+        {
+            *pattern = None;
+        }
+    }
+
+    let handler_traits: Vec<TokenStream2> = table
+        .dispatchers
+        .iter()
+        .filter_map(|dispatcher| {
+            let name = dispatcher.handler_trait.as_ref()?;
+            errors.check(generate_handler_trait(&table, dispatcher, name, &patterns))
+        })
+        .collect();
+    let instruction_enum = table
+        .decoder
+        .as_ref()
+        .and_then(|decoder| errors.check(generate_instruction_enum(&table, decoder, &patterns)));
+
+    let arms = errors
+        .check(collect_arms(&table, &patterns))
+        .map(|mut arms| {
+            // Entries are ordered by the fixed bits of their pattern, expanded variables
+            // don't count. A stable sort keeps the arms of an entry together and the
+            // declaration order between entries of equal specificity
+            if table.priority == Priority::Specificity {
+                let fixed_bits = |arm: &Arm| {
+                    patterns[arm.entry]
+                        .as_ref()
+                        .map_or(0, |pattern| pattern.mask.count_ones())
+                };
+                for arms in &mut arms {
+                    arms.sort_by_key(|arm| std::cmp::Reverse(fixed_bits(arm)));
                 }
             }
-        } else if pattern.wildcard_bits != 0 || !pattern.variables.is_empty() {
-            // Wildcard pattern with no variable bindings - single masked match
-            let mask = pattern.mask;
-            let value = pattern.value;
-            let handler_call = generate_handler_call(
-                &entry.handler,
-                &[],
-                &entry.where_clause,
-                &table.handler_opcode_type,
-            );
+            arms
+        });
+    // The arms of every dispatcher and the decoder match the same opcodes, the first
+    // ones are analyzed
+    let overlap_warnings = arms
+        .as_ref()
+        .and_then(|arms| errors.check(analyze_overlaps(&table, &patterns, &arms[0], bit_width)));
+    let mut arms: Vec<Vec<Arm>> = arms
+        .unwrap_or_default()
+        .into_iter()
+        .map(drop_dominated)
+        .collect();
 
-            let mask_lit = make_literal(mask, bit_width);
-            let value_lit = make_literal(value, bit_width);
-            match_arms.push(quote! {
-                op if op & #mask_lit == #value_lit => { #handler_call }
-            });
-            seen_patterns.push((mask, value));
-        } else {
-            // Fixed pattern - simple exact match
-            let opcode = pattern.value;
-            let full_mask = make_full_mask(bit_width);
-            let key = (full_mask, opcode);
-            if !seen_patterns.contains(&key) {
-                let handler_call = generate_handler_call(
-                    &entry.handler,
-                    &[],
-                    &entry.where_clause,
-                    &table.handler_opcode_type,
-                );
-                let opcode_lit = make_literal(opcode, bit_width);
-                match_arms.push(quote! {
-                    #opcode_lit => { #handler_call }
-                });
-                seen_patterns.push(key);
-            }
-        }
+    // Entries left out because of their errors would show up as unhandled opcodes
+    let complete = patterns.iter().all(Option::is_some);
+    let coverage = match (table.coverage, arms.first()) {
+        (Some((coverage, span)), Some(arms)) if complete => errors.check(generate_coverage(
+            coverage,
+            span,
+            arms,
+            bit_width,
+            opcode_type,
+        )),
+        _ => None,
+    };
+
+    let disassembler_arms = table.disassembler.as_ref().and_then(|_| arms.pop());
+    let encoder = table.decoder.as_ref().and_then(|decoder| {
+        let name = decoder.encoder.as_ref()?;
+        Some(generate_encoder(
+            &table,
+            decoder,
+            name,
+            &patterns,
+            &arms.pop()?,
+            bit_width,
+        ))
+    });
+    let decoder_arms = table.decoder.as_ref().and_then(|_| arms.pop());
+    let mut dispatchers = Vec::new();
+    for (dispatcher, arms) in table.dispatchers.iter().zip(&arms) {
+        dispatchers.extend(errors.check(generate_dispatcher(&table, dispatcher, arms, bit_width)));
+    }
+    if let (Some(decoder), Some(arms)) = (&table.decoder, &decoder_arms) {
+        let dispatcher = decoder.dispatcher(table.strategy);
+        dispatchers.extend(errors.check(generate_dispatcher(&table, &dispatcher, arms, bit_width)));
+    }
+    if let (Some(disassembler), Some(arms)) = (&table.disassembler, &disassembler_arms) {
+        let dispatcher = disassembler.dispatcher(table.strategy);
+        dispatchers.extend(errors.check(generate_dispatcher(&table, &dispatcher, arms, bit_width)));
+    }
+    if let Err(errors) = errors.finish() {
+        return errors.to_compile_error().into();
     }
 
-    // Generate the dispatcher function
     let expanded = quote! {
-        #[inline]
-        pub fn #dispatcher_name(ctx: &mut #context_type, opcode: impl Into<#opcode_type>) {
-            let opcode = opcode.into();
-            match opcode {
-                #(#match_arms)*
-                _ => panic!("Unhandled opcode: 0x{:02X}", opcode),
-            }
-        }
+        #overlap_warnings
+        #coverage
+        #(#handler_traits)*
+        #instruction_enum
+        #encoder
+        #(#dispatchers)*
     };
 
     TokenStream::from(expanded)